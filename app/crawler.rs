@@ -1,35 +1,164 @@
-use dns_collect::collect::{collect, AllDomains};
+use dns_collect::collect::{AllDomains, AllQueries, Baseline, Collector};
+use dns_collect::container::{self, Body, Content, Header};
+use dns_collect::ecs::ClientSubnet;
 use dns_collect::name_server::{parse_name_servers_json, NameServer};
+use dns_collect::observation::{truncate_log, ObservationLog};
+use dns_collect::rdata::{parse_record_type, record_type_name};
+use dns_collect::resume::{self, Manifest, MANIFEST_FILE};
+use dns_collect::store::{Store, STORE_FILE};
 
-use std::fs::{create_dir, File};
+use std::fs::{create_dir, create_dir_all, File};
+use std::io::BufWriter;
 use std::path::{Path, PathBuf};
-use std::thread;
-use std::time::{Duration, Instant};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use trust_dns_proto::rr::RecordType;
 
 const REPEAT: usize = 10;
-const BATCH: usize = 100;
 const SAVE_EVERY: usize = 1000;
+const OBSERVATION_LOG_FLAG: &str = "--observation-log";
+const RESUME_FLAG: &str = "--resume";
+const SQLITE_FLAG: &str = "--sqlite";
 fn print_usage(this: &str) {
     eprintln!(
-        "usage: {} <A | AAAA> <name-servers.json> <top-k-websites.csv> <k> <target_dir>",
+        "usage: {} [--observation-log] [--resume] [--sqlite] <record-type,...> <name-servers.json> <top-k-websites.csv> <k> <target_dir> [ecs-prefix,...]",
         this
     );
+    eprintln!(
+        "--observation-log: write every answer to observations.log instead of the aggregated chunks"
+    );
+    eprintln!(
+        "--resume: continue the crawl in target_dir from the first chunk it did not finish, given the same arguments"
+    );
+    eprintln!(
+        "--sqlite: save the chunks into target_dir/{} instead of one file each",
+        STORE_FILE
+    );
+}
+
+/// Output directory for the results of `name_server` seen from `client_subnet`, e.g.
+/// `Google@192.0.2.0_24`.
+fn slice_dir_name(name_server: &str, client_subnet: Option<ClientSubnet>) -> String {
+    match client_subnet {
+        Some(client_subnet) => format!(
+            "{}@{}",
+            name_server,
+            client_subnet.to_string().replace('/', "_")
+        ),
+        None => name_server.to_owned(),
+    }
+}
+
+/// Files of a probe relative to the target directory, e.g. `A/Google@192.0.2.0_24`.
+fn probe_dir(
+    name_server: &NameServer,
+    record_type: RecordType,
+    slice: Option<ClientSubnet>,
+) -> PathBuf {
+    PathBuf::from(record_type_name(record_type)).join(slice_dir_name(&name_server.name, slice))
+}
+
+/// Header of the files a probe writes for the chunk of ranks `first_rank..=last_rank`.
+fn probe_header(
+    name_server: &NameServer,
+    record_type: RecordType,
+    slice: Option<ClientSubnet>,
+    first_rank: usize,
+    last_rank: usize,
+    content: Content,
+) -> Header {
+    Header {
+        record_type: record_type_name(record_type),
+        name_server: slice_dir_name(&name_server.name, slice),
+        client_subnet: slice.map(|v| v.to_string()),
+        repeat: if name_server.baseline { 1 } else { REPEAT },
+        first_rank,
+        last_rank,
+        differential: name_server.differential,
+        ..Header::new(content)
+    }
 }
 
-fn print_info(name_servers: &[NameServer], k: usize) {
+/// The file a probe writes last for a chunk, and the header it has once the probe is done.
+fn last_file(
+    target_dir: &Path,
+    name_server: &NameServer,
+    record_type: RecordType,
+    slice: Option<ClientSubnet>,
+    first_rank: usize,
+    last_rank: usize,
+) -> (PathBuf, Header) {
+    let (extension, content) = if name_server.baseline {
+        ("baseline", Content::Baseline)
+    } else {
+        ("outcomes", Content::AllQueries)
+    };
+    let path = target_dir
+        .join(probe_dir(name_server, record_type, slice))
+        .join(format!("{}-{}.{}", first_rank, last_rank, extension));
+    let header = probe_header(
+        name_server,
+        record_type,
+        slice,
+        first_rank,
+        last_rank,
+        content,
+    );
+    (path, header)
+}
+
+fn print_info(
+    name_servers: &[NameServer],
+    k: usize,
+    record_types: &[RecordType],
+    slices: &[Option<ClientSubnet>],
+) {
     eprintln!("####### crawler information #######");
     eprintln!();
     eprintln!("all name servers:");
     for n in name_servers {
-        eprintln!("\t{}: {}", n.name, n.host);
+        eprintln!(
+            "\t{}: {} ({:?}, max {} in flight{})",
+            n.name,
+            n.host,
+            n.transport,
+            n.max_in_flight,
+            if n.baseline {
+                ", baseline"
+            } else if n.differential {
+                ", differential"
+            } else {
+                ""
+            }
+        );
     }
     eprintln!("crawl top k:\t\t\t{}", k);
+    eprintln!(
+        "record types:\t\t\t{}",
+        record_types
+            .iter()
+            .map(|v| record_type_name(*v))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
     eprintln!("#repeats per domain:\t\t{}", REPEAT);
-    eprintln!("batch size:\t\t\t{}", BATCH);
-    eprintln!("#batches:\t\t\t{}", (k + BATCH - 1) / BATCH);
-    eprintln!("#queries per batch:\t\t{}", BATCH * REPEAT);
-    eprintln!("#total queries per server:\t{}", k * REPEAT);
+    if slices.iter().any(Option::is_some) {
+        eprintln!(
+            "ECS prefixes:\t\t\t{}",
+            slices
+                .iter()
+                .flatten()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    eprintln!("#chunks:\t\t\t{}", k.div_ceil(SAVE_EVERY));
+    eprintln!(
+        "#total queries per server:\t{}",
+        k * REPEAT * record_types.len() * slices.len()
+    );
     eprintln!("#domains per saved file:\t{}", SAVE_EVERY);
     eprintln!();
     eprintln!("###################################");
@@ -42,11 +171,25 @@ fn print_done(time: Duration) {
     eprintln!();
     eprintln!("############## done! ##############");
     eprintln!();
-    eprintln!("time spent: {} minutes", (time.as_secs() + 60 - 1) / 60);
+    eprintln!("time spent: {} minutes", time.as_secs().div_ceil(60));
     eprintln!();
     eprintln!("#################### ##############");
 }
 
+/// The header of the saved probe `header` describes, from the store if there is one or else from
+/// the file at `path`.
+fn completed(store: Option<&Mutex<Store>>, path: &Path, header: &Header) -> Option<Header> {
+    match store {
+        Some(store) => store.lock().unwrap().completed(header).unwrap(),
+        None => resume::completed(path, header),
+    }
+}
+
+fn save<T: Body>(path: &Path, header: &Header, body: &T) {
+    let file = BufWriter::new(File::create(path).unwrap());
+    container::write(file, header, body).unwrap();
+}
+
 fn take_n(
     n: usize,
     record_iter: &mut impl Iterator<Item = csv::Result<csv::StringRecord>>,
@@ -66,18 +209,22 @@ fn take_n(
     domain_names
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 6 {
+#[tokio::main]
+async fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let observation_log = args.iter().any(|v| v == OBSERVATION_LOG_FLAG);
+    let resume = args.iter().any(|v| v == RESUME_FLAG);
+    let sqlite = args.iter().any(|v| v == SQLITE_FLAG);
+    args.retain(|v| v != OBSERVATION_LOG_FLAG && v != RESUME_FLAG && v != SQLITE_FLAG);
+    if args.len() != 6 && args.len() != 7 {
         print_usage(&args[0]);
         return;
     }
 
-    let record_type = match args[1].as_str() {
-        "A" => RecordType::A,
-        "AAAA" => RecordType::AAAA,
-        _ => panic!("Invalid record type {}", args[1]),
-    };
+    let record_types = args[1]
+        .split(',')
+        .map(|v| parse_record_type(v).expect("Invalid record type"))
+        .collect::<Vec<_>>();
     let name_servers = parse_name_servers_json(Path::new(&args[2]));
     let top_domains_reader = csv::ReaderBuilder::new()
         .has_headers(false)
@@ -86,78 +233,344 @@ fn main() {
     let k = args[4].parse::<usize>().unwrap();
     let target_dir = PathBuf::from(args[5].clone());
     assert!(target_dir.exists());
-    for name_server in name_servers.iter() {
-        create_dir(target_dir.join(&name_server.name)).expect("Error creating name server dir");
+    // without prefixes there is a single slice, queried without the option
+    let slices = match args.get(6) {
+        Some(prefixes) => prefixes
+            .split(',')
+            .map(|v| Some(ClientSubnet::from_str(v).expect("Invalid ECS prefix")))
+            .collect::<Vec<_>>(),
+        None => vec![None],
+    };
+    let mut record_iter = top_domains_reader.into_records().take(k);
+    let domain_names = Arc::new(take_n(k, &mut record_iter));
+    let manifest = Manifest {
+        record_types: record_types.iter().map(|v| record_type_name(*v)).collect(),
+        name_servers: name_servers.iter().map(|v| v.name.clone()).collect(),
+        name_server_checksum: resume::name_server_checksum(&name_servers),
+        client_subnets: slices.iter().flatten().map(ToString::to_string).collect(),
+        repeat: REPEAT,
+        save_every: SAVE_EVERY,
+        observation_log,
+        sqlite,
+        domain_count: domain_names.len(),
+        domain_checksum: resume::domain_checksum(&domain_names),
+    };
+    if resume {
+        if let Err(e) = resume::check_manifest(&target_dir, &manifest) {
+            eprintln!(
+                "cannot resume the crawl in {}: {:?}",
+                target_dir.display(),
+                e
+            );
+            return;
+        }
+    } else {
+        let started = target_dir.join(MANIFEST_FILE).exists()
+            || record_types
+                .iter()
+                .any(|v| target_dir.join(record_type_name(*v)).exists());
+        if started {
+            eprintln!(
+                "{} already holds a crawl, pass {} to continue it",
+                target_dir.display(),
+                RESUME_FLAG
+            );
+            return;
+        }
+        resume::save_manifest(&target_dir, &manifest).expect("Error saving crawl manifest");
+    }
+    // one directory per record type, laid out like a single-type crawl inside
+    for record_type in record_types.iter() {
+        let type_dir = target_dir.join(record_type_name(*record_type));
+        if !resume {
+            create_dir(&type_dir).expect("Error creating record type dir");
+        }
+        for name_server in name_servers.iter() {
+            // the original TTLs do not depend on the client subnet
+            let slices = if name_server.baseline {
+                &[None][..]
+            } else {
+                &slices[..]
+            };
+            for slice in slices.iter() {
+                create_dir_all(type_dir.join(slice_dir_name(&name_server.name, *slice)))
+                    .expect("Error creating name server dir");
+            }
+        }
     }
 
-    let n_batches = (k + BATCH - 1) / BATCH;
+    let store = if sqlite {
+        let store = Store::open(&target_dir.join(STORE_FILE)).expect("Error opening store");
+        Some(Arc::new(Mutex::new(store)))
+    } else {
+        None
+    };
+
+    let n_chunks = k.div_ceil(SAVE_EVERY);
 
-    print_info(name_servers.as_slice(), k);
+    print_info(name_servers.as_slice(), k, &record_types, &slices);
 
-    let mut record_iter = top_domains_reader.into_records().take(k).peekable();
-    let mut batch_counter = 1usize;
-    let mut accumulated = 0usize;
+    let probes = record_types
+        .iter()
+        .flat_map(|v| slices.iter().map(move |slice| (*v, *slice)))
+        .collect::<Vec<_>>();
 
     let now = Instant::now();
 
-    loop {
-        let domain_names = take_n(SAVE_EVERY, &mut record_iter);
-        if domain_names.is_empty() {
-            break;
-        }
-        let handles = name_servers
-            .iter()
-            .cloned()
-            .map(|name_server| {
-                let domain_names = domain_names.clone();
-                let target_dir = target_dir.clone();
-                thread::spawn(move || {
-                    let mut all_domains = AllDomains::new();
+    // every name server works through the whole list on its own, at its own pace
+    let handles = name_servers
+        .iter()
+        .cloned()
+        .map(|name_server| {
+            let domain_names = domain_names.clone();
+            let target_dir = target_dir.clone();
+            let store = store.clone();
+            let probes = if name_server.baseline {
+                record_types.iter().map(|v| (*v, None)).collect()
+            } else {
+                probes.clone()
+            };
+            tokio::spawn(async move {
+                let mut collector = match Collector::new(&name_server) {
+                    Ok(collector) => collector,
+                    Err(e) => {
+                        eprintln!("{}: cannot set up transport: {:?}", name_server.name, e);
+                        return;
+                    }
+                };
+                let ranks = |i: usize, chunk: &[String]| {
+                    (i * SAVE_EVERY + 1, i * SAVE_EVERY + chunk.len())
+                };
+                let resume_from = if resume {
                     domain_names
-                        .as_slice()
-                        .chunks(BATCH)
+                        .chunks(SAVE_EVERY)
                         .enumerate()
-                        .for_each(|(i, batch)| {
+                        .position(|(i, chunk)| {
+                            let (first_rank, last_rank) = ranks(i, chunk);
+                            probes.iter().any(|(record_type, slice)| {
+                                let (path, header) = last_file(
+                                    &target_dir,
+                                    &name_server,
+                                    *record_type,
+                                    *slice,
+                                    first_rank,
+                                    last_rank,
+                                );
+                                completed(store.as_deref(), &path, &header).is_none()
+                            })
+                        })
+                        .unwrap_or(n_chunks)
+                } else {
+                    0
+                };
+                if resume && resume_from == n_chunks {
+                    eprintln!("{}: all chunks done already", name_server.name);
+                } else if resume {
+                    eprintln!(
+                        "{}: resuming at chunk {}/{}",
+                        name_server.name,
+                        resume_from + 1,
+                        n_chunks
+                    );
+                }
+                // a log may hold part of the chunk that is about to be collected again
+                if resume && observation_log && !name_server.baseline && resume_from < n_chunks {
+                    for (record_type, slice) in probes.iter().cloned() {
+                        let cutoff = match resume_from.checked_sub(1) {
+                            Some(i) => {
+                                let chunk = domain_names.chunks(SAVE_EVERY).nth(i).unwrap();
+                                let (first_rank, last_rank) = ranks(i, chunk);
+                                let (path, header) = last_file(
+                                    &target_dir,
+                                    &name_server,
+                                    record_type,
+                                    slice,
+                                    first_rank,
+                                    last_rank,
+                                );
+                                completed(store.as_deref(), &path, &header)
+                                    .unwrap()
+                                    .finished_at
+                            }
+                            None => SystemTime::UNIX_EPOCH,
+                        };
+                        let path = target_dir
+                            .join(probe_dir(&name_server, record_type, slice))
+                            .join("observations.log");
+                        let dropped = truncate_log(&path, cutoff).expect("Error truncating observation log");
+                        if dropped > 0 {
                             eprintln!(
-                                "{}: processing batch {}/{} ...",
+                                "{}: dropped {} observations of the unfinished chunk from {}",
                                 name_server.name,
-                                batch_counter + i,
-                                n_batches
+                                dropped,
+                                path.to_str().unwrap()
                             );
-                            let meta = collect(&name_server, batch, record_type, REPEAT, &mut all_domains);
-                            eprintln!(
-                                "{}: batch result = #queries {}, #reponses(valid/all) {}/{}, #repeat(valid/all) {}/{}, #in_queries {}/{}, #not_in_queries {}/{}",
-                                name_server.name,
-                                BATCH * REPEAT,
-                                meta.response_valid,
-                                meta.response_count,
-                                meta.repeat_valid,
-                                meta.repeat_count,
-                                meta.in_queries,
-                                meta.response_valid,
-                                meta.not_in_queries,
-                                meta.response_valid,
-                                );
-                        });
-                    let filename = format!(
-                        "{}-{}.txt",
-                        accumulated + 1,
-                        accumulated + domain_names.len()
-                    );
-                    let file_path = target_dir.join(&name_server.name).join(&filename);
-                    let mut file = File::create(&file_path).unwrap();
+                        }
+                    }
+                }
+                let mut accumulated = 0usize;
+                'chunks: for (i, chunk) in domain_names.chunks(SAVE_EVERY).enumerate() {
+                    if i < resume_from {
+                        accumulated += chunk.len();
+                        continue;
+                    }
                     eprintln!(
-                        "{}: saving {} ...",
+                        "{}: processing chunk {}/{} ...",
                         name_server.name,
-                        file_path.to_str().unwrap()
+                        i + 1,
+                        n_chunks
                     );
-                    bincode::serialize_into(&mut file, &all_domains).unwrap();
-                })
+                    // all types and slices of a chunk are collected back to back to keep them
+                    // comparable
+                    for (record_type, slice) in probes.iter().cloned() {
+                        let dir = probe_dir(&name_server, record_type, slice);
+                        let label = dir.to_str().unwrap().to_owned();
+                        let filename =
+                            format!("{}-{}.txt", accumulated + 1, accumulated + chunk.len());
+                        let file_path = target_dir.join(&dir).join(&filename);
+                        let header = |content| {
+                            probe_header(
+                                &name_server,
+                                record_type,
+                                slice,
+                                accumulated + 1,
+                                accumulated + chunk.len(),
+                                content,
+                            )
+                        };
+                        let started_at = SystemTime::now();
+                        if name_server.baseline {
+                            let mut baseline = Baseline::new();
+                            let meta = collector
+                                .collect_baseline(chunk, record_type, &mut baseline)
+                                .await;
+                            eprintln!(
+                                "{}: chunk baseline = #queries {}, #rrsets {}, #responses {}, #give_ups {}",
+                                label,
+                                chunk.len(),
+                                baseline.len(),
+                                meta.response_count,
+                                meta.give_ups,
+                            );
+                            // a chunk cut short is collected again on resume
+                            if meta.budget_exhausted > 0 {
+                                eprintln!(
+                                    "{}: query budget spent, stopping without saving the chunk",
+                                    name_server.name
+                                );
+                                break 'chunks;
+                            }
+                            let header = Header {
+                                started_at,
+                                finished_at: SystemTime::now(),
+                                ..header(Content::Baseline)
+                            };
+                            match &store {
+                                Some(store) => {
+                                    eprintln!("{}: saving into {} ...", label, STORE_FILE);
+                                    let mut store = store.lock().unwrap();
+                                    store.save_baseline(&header, &baseline).unwrap();
+                                }
+                                None => {
+                                    let file_path = file_path.with_extension("baseline");
+                                    eprintln!("{}: saving {} ...", label, file_path.to_str().unwrap());
+                                    save(&file_path, &header, &baseline);
+                                }
+                            }
+                            continue;
+                        }
+                        collector.set_client_subnet(slice);
+                        if observation_log {
+                            let path = target_dir.join(&dir).join("observations.log");
+                            let log = ObservationLog::open(&path, &name_server.name)
+                                .expect("Error opening observation log");
+                            collector.set_observation_log(Some(log));
+                        }
+                        let mut all_domains = AllDomains::new();
+                        let mut all_queries = AllQueries::new();
+                        let meta = collector
+                            .collect(
+                                chunk,
+                                record_type,
+                                REPEAT,
+                                &mut all_domains,
+                                &mut all_queries,
+                            )
+                            .await;
+                        eprintln!(
+                            "{}: chunk result = #queries {}, #reponses(valid/all) {}/{}, #repeat(valid/all) {}/{}, #in_queries {}/{}, #not_in_queries {}/{}, #tcp_fallbacks {}, throttled {:.1}s, #over_budget {}, #timeouts {}, #retries {}, #give_ups {}",
+                            label,
+                            chunk.len() * REPEAT,
+                            meta.response_valid,
+                            meta.response_count,
+                            meta.repeat_valid,
+                            meta.repeat_count,
+                            meta.in_queries,
+                            meta.response_valid,
+                            meta.not_in_queries,
+                            meta.response_valid,
+                            meta.tcp_fallbacks,
+                            meta.throttled.as_secs_f64(),
+                            meta.budget_exhausted,
+                            meta.timeouts,
+                            meta.retries,
+                            meta.give_ups,
+                        );
+                        if name_server.differential {
+                            eprintln!(
+                                "{}: chunk controls = #resolved {}/{}, #missed_by_snooping {}",
+                                label,
+                                meta.controls,
+                                chunk.len(),
+                                meta.snooping_misses,
+                            );
+                        }
+                        if meta.budget_exhausted > 0 {
+                            eprintln!(
+                                "{}: query budget spent, stopping without saving the chunk",
+                                name_server.name
+                            );
+                            break 'chunks;
+                        }
+                        let finished_at = SystemTime::now();
+                        let domains_header = Header {
+                            started_at,
+                            finished_at,
+                            ..header(Content::AllDomains)
+                        };
+                        let queries_header = Header {
+                            started_at,
+                            finished_at,
+                            ..header(Content::AllQueries)
+                        };
+                        // the log already holds everything the aggregate would; the outcomes go
+                        // last as they mark the probe done
+                        match &store {
+                            Some(store) => {
+                                eprintln!("{}: saving into {} ...", label, STORE_FILE);
+                                let mut store = store.lock().unwrap();
+                                if !observation_log {
+                                    store.save_domains(&domains_header, &all_domains).unwrap();
+                                }
+                                store.save_queries(&queries_header, &all_queries).unwrap();
+                            }
+                            None => {
+                                if !observation_log {
+                                    eprintln!("{}: saving {} ...", label, file_path.to_str().unwrap());
+                                    save(&file_path, &domains_header, &all_domains);
+                                }
+                                let file_path = file_path.with_extension("outcomes");
+                                save(&file_path, &queries_header, &all_queries);
+                            }
+                        }
+                    }
+                    accumulated += chunk.len();
+                }
             })
-            .collect::<Vec<_>>();
-        handles.into_iter().for_each(|h| h.join().unwrap());
-        batch_counter += (domain_names.len() + BATCH - 1) / BATCH;
-        accumulated += domain_names.len();
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.await.unwrap();
     }
     print_done(now.elapsed());
 }
@@ -1,10 +1,18 @@
-use dns_collect::collect::AllDomains;
+use dns_collect::collect::{AllDomains, AllQueries, Baseline, QueryStat};
+use dns_collect::container::{self, Body, Content};
+use dns_collect::export::{exporter, rows, ExportFormat};
+use dns_collect::observation::{aggregate, read_log};
+use dns_collect::rdata::parse_record_type;
 use dns_collect::record_wrapper::RecordWrapper;
-use std::collections::HashSet;
+use dns_collect::store::{Store, STORE_FILE};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::fs::{read_dir, File};
-use std::path::Path;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
+use trust_dns_proto::op::ResponseCode;
 use trust_dns_proto::rr::Name;
 
 #[derive(Eq, Clone)]
@@ -25,27 +33,60 @@ impl<'a> std::hash::Hash for DnsAnswers<'a> {
             i.hash(hasher);
         }
     }
-
 }
 
 fn print_usage(this: &str) {
-    eprintln!("usage: {} <source_dir> <cisco-top-1m.csv>", this);
+    eprintln!("usage: {} <source> <cisco-top-1m.csv>", this);
+    eprintln!(
+        "       {} export [--format parquet|jsonl|csv] <source> [output]",
+        this
+    );
+    eprintln!(
+        "<source> is a crawl's target dir, one of its record type dirs or its {}",
+        STORE_FILE
+    );
 }
 
-fn read_from_dir(dir: &Path) -> AllDomains {
-    let mut all_domains = AllDomains::new();
+/// Merges every `<extension>` chunk file in `dir`.
+fn read_from_dir<T>(dir: &Path, extension: &str) -> T
+where
+    T: Default + Extend<<T as IntoIterator>::Item> + IntoIterator + Body,
+{
+    let mut all = T::default();
     if dir.is_dir() {
         for entry in read_dir(dir).unwrap() {
-            let mut file = File::open(entry.unwrap().path()).unwrap();
-            let domains: AllDomains = bincode::deserialize_from(&mut file).unwrap();
-            all_domains.extend(domains.into_iter());
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|v| v.to_str()) != Some(extension) {
+                continue;
+            }
+            let file = BufReader::new(File::open(&path).unwrap());
+            let (_, chunk) = container::read::<T>(file)
+                .unwrap_or_else(|e| panic!("cannot read {}: {:?}", path.display(), e));
+            all.extend(chunk);
         }
     } else {
         panic!("{} is not a directory", dir.display());
     }
+    all
+}
+
+/// The aggregated chunks of a name server directory, or the aggregate derived from its
+/// observation log when the crawl wrote one instead.
+fn read_domains(dir: &Path) -> AllDomains {
+    let mut all_domains: AllDomains = read_from_dir(dir, "txt");
+    let log = dir.join("observations.log");
+    if log.is_file() {
+        aggregate(&read_log(&log).unwrap(), &mut all_domains);
+    }
     all_domains
 }
 
+fn has_extension(dir: &Path, extension: &str) -> bool {
+    read_dir(dir)
+        .unwrap()
+        .any(|v| v.unwrap().path().extension().and_then(|v| v.to_str()) == Some(extension))
+}
+
 fn take_n(
     n: usize,
     record_iter: &mut impl Iterator<Item = csv::Result<csv::StringRecord>>,
@@ -128,6 +169,9 @@ fn print_overlaps(all_ns: &[(String, AllDomains)]) {
     union1_str.pop();
     union1_str.pop();
     println!("|{}| = {}", union1_str, union1.len());
+    if all_ns.len() < 2 {
+        return;
+    }
 
     let mut union2 = HashSet::<&&Name>::new();
     let mut union2_str = String::new();
@@ -137,13 +181,16 @@ fn print_overlaps(all_ns: &[(String, AllDomains)]) {
                 .intersection(&all_keys[j])
                 .collect::<HashSet<_>>();
             println!("|{} ∩ {}| = {}", all_ns[i].0, all_ns[j].0, intersect.len());
-            union2.extend(intersect.into_iter());
+            union2.extend(intersect);
             write!(&mut union2_str, "({} ∩ {}) ∪ ", all_ns[i].0, all_ns[j].0).unwrap();
         }
     }
     union2_str.pop();
     union2_str.pop();
     println!("|{}| = {}", union2_str, union2.len());
+    if all_ns.len() < 3 {
+        return;
+    }
 
     let mut union3 = HashSet::<&Name>::new();
     let mut union3_str = String::new();
@@ -165,7 +212,7 @@ fn print_overlaps(all_ns: &[(String, AllDomains)]) {
                     all_ns[k].0,
                     intersect.len()
                 );
-                union3.extend(intersect.into_iter());
+                union3.extend(intersect);
                 write!(
                     &mut union3_str,
                     "({} ∩ {} ∩ {}) ∪ ",
@@ -190,16 +237,22 @@ fn print_overlaps_record(all_ns: &[(String, AllDomains)]) {
                 .map(|(k, v)| (k, DnsAnswers(v.keys().collect())))
                 .collect::<HashSet<(&Name, DnsAnswers)>>()
         })
-    .collect::<Vec<_>>();
+        .collect::<Vec<_>>();
     let mut union1 = HashSet::<&(&Name, DnsAnswers)>::new();
     let mut union1_str = String::new();
-    all_records.iter().zip(all_ns.iter().map(|(k, _)| k)).for_each(|(v, k)| {
-        union1.extend(v);
-        write!(&mut union1_str, "{} ∪ ", k).unwrap();
-    });
+    all_records
+        .iter()
+        .zip(all_ns.iter().map(|(k, _)| k))
+        .for_each(|(v, k)| {
+            union1.extend(v);
+            write!(&mut union1_str, "{} ∪ ", k).unwrap();
+        });
     union1_str.pop();
     union1_str.pop();
     println!("|{}| = {}", union1_str, union1.len());
+    if all_ns.len() < 2 {
+        return;
+    }
 
     let mut union2 = HashSet::<&(&Name, DnsAnswers)>::new();
     let mut union2_str = String::new();
@@ -209,13 +262,16 @@ fn print_overlaps_record(all_ns: &[(String, AllDomains)]) {
                 .intersection(&all_records[j])
                 .collect::<HashSet<_>>();
             println!("|{} ∩ {}| = {}", all_ns[i].0, all_ns[j].0, intersect.len());
-            union2.extend(intersect.into_iter());
+            union2.extend(intersect);
             write!(&mut union2_str, "({} ∩ {}) ∪ ", all_ns[i].0, all_ns[j].0).unwrap();
         }
     }
     union2_str.pop();
     union2_str.pop();
     println!("|{}| = {}", union2_str, union2.len());
+    if all_ns.len() < 3 {
+        return;
+    }
 
     let mut union3 = HashSet::<(&Name, DnsAnswers)>::new();
     let mut union3_str = String::new();
@@ -237,7 +293,7 @@ fn print_overlaps_record(all_ns: &[(String, AllDomains)]) {
                     all_ns[k].0,
                     intersect.len()
                 );
-                union3.extend(intersect.into_iter());
+                union3.extend(intersect);
                 write!(
                     &mut union3_str,
                     "({} ∩ {} ∩ {}) ∪ ",
@@ -254,17 +310,340 @@ fn print_overlaps_record(all_ns: &[(String, AllDomains)]) {
     println!("|{}| = {}", union3_str, union3.len());
 }
 
-pub fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 3 {
-        print_usage(&args[0]);
-        std::process::exit(1);
+/// Sorts a queried name by what its probes got back: any answer at all wins, otherwise the most
+/// frequent response code.
+fn classify(stat: &QueryStat) -> &'static str {
+    if stat.outcomes.keys().any(|outcome| outcome.answer_count > 0) {
+        return "answered";
+    }
+    let mut rcode_counts = Vec::<(ResponseCode, usize)>::new();
+    for (outcome, count) in stat.outcomes.iter() {
+        match rcode_counts
+            .iter_mut()
+            .find(|(rcode, _)| *rcode == outcome.response_code())
+        {
+            Some((_, total)) => *total += count,
+            None => rcode_counts.push((outcome.response_code(), *count)),
+        }
+    }
+    match rcode_counts.iter().max_by_key(|(_, count)| *count) {
+        Some((ResponseCode::NoError, _)) => "empty",
+        Some((ResponseCode::Refused, _)) => "refused",
+        Some((ResponseCode::ServFail, _)) => "servfail",
+        Some((ResponseCode::NXDomain, _)) => "nxdomain",
+        _ => "other",
+    }
+}
+
+fn print_outcomes(all_queries: &[(String, AllQueries)]) {
+    let classes = [
+        "answered", "empty", "refused", "servfail", "nxdomain", "other",
+    ];
+    for (ns, queries) in all_queries.iter() {
+        let mut buf = String::new();
+        for class in classes.iter() {
+            let count = queries.values().filter(|v| classify(v) == *class).count();
+            write!(&mut buf, "{} {}, ", class, count).unwrap();
+        }
+        buf.truncate(buf.len() - 2);
+        println!("{}: {}", ns, buf);
+    }
+}
+
+/// Median over the per-name median latencies of the names in each class, to see whether cache
+/// hits and misses separate by timing.
+fn print_latencies(all_queries: &[(String, AllQueries)]) {
+    for (ns, queries) in all_queries.iter() {
+        let mut buf = String::new();
+        for class in ["answered", "empty"].iter() {
+            let mut medians = queries
+                .values()
+                .filter(|v| classify(v) == *class)
+                .filter_map(|v| v.latency())
+                .map(|v| v.median)
+                .collect::<Vec<_>>();
+            medians.sort_unstable();
+            match medians.get(medians.len() / 2) {
+                Some(median) => write!(
+                    &mut buf,
+                    "{} {:.1}ms (n={}), ",
+                    class,
+                    median.as_secs_f64() * 1000.0,
+                    medians.len()
+                )
+                .unwrap(),
+                None => write!(&mut buf, "{} - (n=0), ", class).unwrap(),
+            }
+        }
+        buf.truncate(buf.len() - 2);
+        println!("{}: {}", ns, buf);
+    }
+}
+
+/// For the name servers crawled in differential mode: how many names resolved recursively and
+/// how many of those the non-recursive probes did not see.
+fn print_differential(all_queries: &[(String, AllQueries)]) {
+    for (ns, queries) in all_queries.iter() {
+        let controls = queries.values().filter(|v| v.control.is_some()).count();
+        if controls == 0 {
+            continue;
+        }
+        let resolved = queries
+            .values()
+            .filter(|v| v.control.is_some_and(|control| control.answer_count > 0))
+            .count();
+        let missed = queries.values().filter(|v| v.missed_by_snooping()).count();
+        println!(
+            "{}: controls {}, resolved {}, missed by snooping {} ({:.1}%)",
+            ns,
+            controls,
+            resolved,
+            missed,
+            100.0 * missed as f64 / resolved.max(1) as f64
+        );
+    }
+}
+
+/// Names answered through CNAME chains, by chain depth, and the chain targets that were only
+/// cached because a probed name pointed at them.
+fn print_chains(all_ns: &[(String, AllDomains)], all_queries: &[(String, AllQueries)]) {
+    for ((ns, domains), (_, queries)) in all_ns.iter().zip(all_queries.iter()) {
+        let mut depths = Vec::<usize>::new();
+        let mut targets = HashSet::<&Name>::new();
+        for stat in queries.values() {
+            let depth = stat.chain_depth();
+            if depth == 0 {
+                continue;
+            }
+            if depths.len() < depth {
+                depths.resize(depth, 0);
+            }
+            depths[depth - 1] += 1;
+            targets.extend(stat.chains.keys().flat_map(|chain| chain[1..].iter()));
+        }
+        let via_chain = targets
+            .iter()
+            .filter(|v| domains.contains_key(**v) && !queries.contains_key(**v))
+            .count();
+        let mut buf = String::new();
+        for (i, count) in depths.iter().enumerate() {
+            write!(&mut buf, "depth {} {}, ", i + 1, count).unwrap();
+        }
+        println!(
+            "{}: chained {}, {}cached via chain {}",
+            ns,
+            depths.iter().sum::<usize>(),
+            buf,
+            via_chain
+        );
+    }
+}
+
+/// When records entered each name server's cache, estimated from their remaining TTLs for the
+/// records whose original TTL is known from an RRSIG or the baseline, bucketed by 10 minutes from the first fill seen.
+fn print_cache_fills(all_ns: &[(String, AllDomains)], baseline: &Baseline) {
+    const BUCKET: u64 = 600;
+    for (ns, domains) in all_ns.iter() {
+        let stats = domains.values().flat_map(|v| v.iter()).collect::<Vec<_>>();
+        let mut fills = Vec::new();
+        let mut known = 0;
+        let mut refills = 0;
+        let mut observed = Vec::new();
+        for (record, stat) in stats.iter() {
+            if let Some(original_ttl) = stat.original_ttl.or_else(|| original_ttl(baseline, record))
+            {
+                let record_fills = stat.cache_fills(original_ttl);
+                known += 1;
+                refills += record_fills.len().saturating_sub(1);
+                fills.extend(record_fills);
+                observed.extend(stat.observations.iter().map(|v| v.observed_at));
+            }
+        }
+        fills.sort_unstable();
+        // refills per hour over the time the crawl was watching these records
+        let span = match (observed.iter().min(), observed.iter().max()) {
+            (Some(first), Some(last)) => last.duration_since(*first).unwrap_or_default(),
+            _ => Duration::default(),
+        };
+        let rate = refills as f64 * 3600.0 / span.as_secs_f64().max(1.0);
+        let mut timeline = Vec::<(u64, usize)>::new();
+        if let Some(first) = fills.first() {
+            for fill in fills.iter() {
+                let bucket = fill.duration_since(*first).unwrap_or_default().as_secs() / BUCKET;
+                match timeline.last_mut() {
+                    Some((last, count)) if *last == bucket => *count += 1,
+                    _ => timeline.push((bucket, 1)),
+                }
+            }
+        }
+        let mut buf = String::new();
+        for (bucket, count) in timeline.iter() {
+            write!(&mut buf, ", +{}m {}", bucket * BUCKET / 60, count).unwrap();
+        }
+        println!(
+            "{}: original ttl known {}/{}, fills {}, refills {} ({:.1}/h){}",
+            ns,
+            known,
+            stats.len(),
+            fills.len(),
+            refills,
+            rate,
+            buf
+        );
+    }
+}
+
+fn original_ttl(baseline: &Baseline, record: &RecordWrapper) -> Option<u32> {
+    let record = record.record();
+    baseline
+        .get(&(record.name().clone(), record.rr_type().into()))
+        .copied()
+}
+
+/// Compares the remaining TTLs each name server gave out with the original ones from the
+/// baseline name server: how fresh the cached records were, and whether TTLs were stretched
+/// past the original or capped below it.
+fn print_baseline(all_ns: &[(String, AllDomains)], baseline: &Baseline) {
+    if baseline.is_empty() {
+        return;
+    }
+    for (ns, domains) in all_ns.iter() {
+        // the highest TTL seen per record with a known original TTL
+        let compared = domains
+            .values()
+            .flat_map(|v| v.iter())
+            .filter_map(|(record, stat)| {
+                Some((original_ttl(baseline, record)?, *stat.ttls.iter().max()?))
+            })
+            .collect::<Vec<_>>();
+        let fresh = compared
+            .iter()
+            .filter(|(orig, ttl)| ttl * 2 >= *orig)
+            .count();
+        let expiring = compared
+            .iter()
+            .filter(|(orig, ttl)| ttl * 10 < *orig)
+            .count();
+        let stretched = compared.iter().filter(|(orig, ttl)| ttl > orig).count();
+        // a cap shows as the highest TTL given out standing in for longer original ones
+        let cap = compared.iter().map(|(_, ttl)| *ttl).max().unwrap_or(0);
+        let capped = compared
+            .iter()
+            .filter(|(orig, ttl)| *ttl == cap && *orig > cap)
+            .count();
+        println!(
+            "{}: with baseline {}/{}, fresh {}, nearly expired {}, stretched {}, capped at {}s {}",
+            ns,
+            compared.len(),
+            domains.values().map(|v| v.len()).sum::<usize>(),
+            fresh,
+            expiring,
+            stretched,
+            cap,
+            capped
+        );
     }
+}
+
+/// Compares the cache slices of name servers crawled with several EDNS Client Subnet prefixes,
+/// i.e. the `<name server>@<prefix>` directories written by the crawler.
+fn print_slices(all_ns: &[(String, AllDomains)], all_queries: &[(String, AllQueries)]) {
+    let mut by_ns = Vec::<(&str, Vec<usize>)>::new();
+    for (i, (dir_name, _)) in all_ns.iter().enumerate() {
+        if let Some((ns, _)) = dir_name.split_once('@') {
+            match by_ns.iter_mut().find(|(name, _)| *name == ns) {
+                Some((_, slices)) => slices.push(i),
+                None => by_ns.push((ns, vec![i])),
+            }
+        }
+    }
+    for (ns, slices) in by_ns.iter() {
+        let mut buf = String::new();
+        let mut in_all: Option<HashSet<&Name>> = None;
+        let mut seen = Vec::<&Name>::new();
+        for i in slices.iter() {
+            let (dir_name, domains) = &all_ns[*i];
+            write!(
+                &mut buf,
+                "{} {}, ",
+                dir_name.split_once('@').unwrap().1.replace('_', "/"),
+                domains.len()
+            )
+            .unwrap();
+            let names = domains.keys().collect::<HashSet<_>>();
+            in_all = Some(match in_all {
+                Some(v) => v.intersection(&names).cloned().collect(),
+                None => names,
+            });
+            seen.extend(domains.keys());
+        }
+        let mut slice_counts = HashMap::<&Name, usize>::new();
+        for name in seen {
+            *slice_counts.entry(name).or_default() += 1;
+        }
+        let only_one = slice_counts.values().filter(|v| **v == 1).count();
+        let (scoped, answered) = slices
+            .iter()
+            .flat_map(|i| all_queries[*i].1.values())
+            .flat_map(|v| v.outcomes.iter())
+            .filter(|(outcome, _)| outcome.answer_count > 0)
+            .fold(
+                (0, 0),
+                |(scoped, answered), (outcome, count)| match outcome.client_subnet_scope {
+                    Some(scope) if scope > 0 => (scoped + count, answered + count),
+                    _ => (scoped, answered + count),
+                },
+            );
+        println!(
+            "{}: {}in all slices {}, in one slice only {}, scoped answers {}/{}",
+            ns,
+            buf,
+            in_all.map_or(0, |v| v.len()),
+            only_one,
+            scoped,
+            answered
+        );
+    }
+}
+
+/// The record type directories of a crawl, sorted by name; empty for the older layout with the
+/// name server directories right under `dns_dir`.
+fn record_type_dirs(dns_dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut dirs = Vec::new();
+    for entry in read_dir(dns_dir).unwrap() {
+        let path = entry.unwrap().path();
+        // such as the crawl manifest
+        if !path.is_dir() {
+            continue;
+        }
+        let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+        if parse_record_type(&name).is_err() {
+            return Vec::new();
+        }
+        dirs.push((name, path));
+    }
+    dirs.sort();
+    dirs
+}
+
+/// Everything saved for the name servers of one record type: their records and query outcomes
+/// by name server, and the original TTLs from baseline name servers.
+type Results = (
+    Vec<(String, AllDomains)>,
+    Vec<(String, AllQueries)>,
+    Baseline,
+);
+
+fn read_results_dir(dns_dir: &Path) -> Results {
     let mut all_ns = Vec::<(String, AllDomains)>::new();
-    let dns_dir = Path::new(&args[1]);
+    let mut all_queries = Vec::<(String, AllQueries)>::new();
+    let mut baseline = Baseline::new();
     if dns_dir.is_dir() {
         for entry in read_dir(dns_dir).unwrap() {
             let entry = entry.unwrap();
+            if !entry.path().is_dir() {
+                continue;
+            }
             let dir_name = entry
                 .path()
                 .file_name()
@@ -272,14 +651,241 @@ pub fn main() {
                 .to_str()
                 .unwrap()
                 .to_owned();
-            all_ns.push((dir_name, read_from_dir(&entry.path())));
+            // the baseline name server is not one of the caches being compared
+            if has_extension(&entry.path(), "baseline") {
+                baseline.extend(read_from_dir::<Baseline>(&entry.path(), "baseline"));
+                continue;
+            }
+            all_ns.push((dir_name.clone(), read_domains(&entry.path())));
+            all_queries.push((dir_name, read_from_dir(&entry.path(), "outcomes")));
         }
     } else {
         panic!("{} is not a directory", dns_dir.display());
     }
-    print_dist(args[0].as_ref(), &all_ns[..]);
+    (all_ns, all_queries, baseline)
+}
+
+/// Like `read_results_dir`, from a store. Observation logs are still files next to the store.
+fn read_results_store(store: &Store, store_path: &Path, record_type: &str) -> Results {
+    let mut all_ns = Vec::<(String, AllDomains)>::new();
+    let mut all_queries = Vec::<(String, AllQueries)>::new();
+    let mut baseline = Baseline::new();
+    let mut name_servers = Vec::new();
+    for header in store.headers().unwrap() {
+        let name_server = (header.name_server, header.content == Content::Baseline);
+        if header.record_type == record_type && !name_servers.contains(&name_server) {
+            name_servers.push(name_server);
+        }
+    }
+    for (name_server, is_baseline) in name_servers {
+        if is_baseline {
+            baseline.extend(store.read_baseline(record_type, &name_server).unwrap());
+            continue;
+        }
+        let mut all_domains = store.read_domains(record_type, &name_server).unwrap();
+        let log = store_path
+            .with_file_name(record_type)
+            .join(&name_server)
+            .join("observations.log");
+        if log.is_file() {
+            aggregate(&read_log(&log).unwrap(), &mut all_domains);
+        }
+        all_ns.push((name_server.clone(), all_domains));
+        all_queries.push((
+            name_server.clone(),
+            store.read_queries(record_type, &name_server).unwrap(),
+        ));
+    }
+    (all_ns, all_queries, baseline)
+}
+
+fn print_report(results: Results, top_domains: &str) {
+    let (all_ns, all_queries, baseline) = results;
+    print_dist(top_domains, &all_ns[..]);
     println!("=== Domain Name Stats ===");
     print_overlaps(&all_ns[..]);
     println!("=== Record Stats ===");
     print_overlaps_record(&all_ns[..]);
+    println!("=== Query Outcome Stats ===");
+    print_outcomes(&all_queries[..]);
+    println!("=== Latency Stats ===");
+    print_latencies(&all_queries[..]);
+    println!("=== Differential Probing Stats ===");
+    print_differential(&all_queries[..]);
+    println!("=== Cache Fill Stats ===");
+    print_cache_fills(&all_ns[..], &baseline);
+    println!("=== TTL Baseline Stats ===");
+    print_baseline(&all_ns[..], &baseline);
+    println!("=== CNAME Chain Stats ===");
+    print_chains(&all_ns[..], &all_queries[..]);
+    println!("=== ECS Slice Stats ===");
+    print_slices(&all_ns[..], &all_queries[..]);
+}
+
+/// The SQLite store `source` names, either directly or as the crawl directory holding it.
+fn store_path(source: &Path) -> Option<PathBuf> {
+    if source.is_file() {
+        Some(source.to_owned())
+    } else if source.join(STORE_FILE).is_file() {
+        Some(source.join(STORE_FILE))
+    } else {
+        None
+    }
+}
+
+/// Calls `f` with the results of each record type in `source`, which is `None` for a crawl from
+/// before record type directories.
+fn for_each_results(source: &Path, mut f: impl FnMut(Option<&str>, Results)) {
+    if let Some(store_path) = store_path(source) {
+        let store = Store::open(&store_path).unwrap();
+        let mut record_types = store
+            .headers()
+            .unwrap()
+            .into_iter()
+            .map(|v| v.record_type)
+            .collect::<Vec<_>>();
+        record_types.sort();
+        record_types.dedup();
+        for record_type in record_types.iter() {
+            f(
+                Some(record_type),
+                read_results_store(&store, &store_path, record_type),
+            );
+        }
+        return;
+    }
+    if !source.is_dir() {
+        panic!("{} is not a directory", source.display());
+    }
+    let record_types = record_type_dirs(source);
+    if record_types.is_empty() {
+        f(None, read_results_dir(source));
+    }
+    for (record_type, dir) in record_types.iter() {
+        f(Some(record_type), read_results_dir(dir));
+    }
+}
+
+/// Calls `f` with the name server, record type and records of every chunk in `source`, reading
+/// one file or store run at a time. An observation log counts as one chunk.
+fn for_each_chunk(source: &Path, mut f: impl FnMut(&str, Option<&str>, &AllDomains)) {
+    let mut logs = Vec::new();
+    if let Some(store_path) = store_path(source) {
+        let store = Store::open(&store_path).unwrap();
+        store
+            .for_each_domains_chunk(|header, all_domains| {
+                f(&header.name_server, Some(&header.record_type), &all_domains)
+            })
+            .unwrap();
+        let mut probes = store
+            .headers()
+            .unwrap()
+            .into_iter()
+            .filter(|v| v.content == Content::AllQueries)
+            .map(|v| (v.name_server, v.record_type))
+            .collect::<Vec<_>>();
+        probes.sort();
+        probes.dedup();
+        for (name_server, record_type) in probes {
+            let log = store_path
+                .with_file_name(&record_type)
+                .join(&name_server)
+                .join("observations.log");
+            logs.push((name_server, Some(record_type), log));
+        }
+    } else {
+        let record_types = match record_type_dirs(source) {
+            dirs if dirs.is_empty() => vec![(None, source.to_owned())],
+            dirs => dirs.into_iter().map(|(k, v)| (Some(k), v)).collect(),
+        };
+        for (record_type, dir) in record_types {
+            let mut ns_dirs = read_dir(&dir)
+                .unwrap()
+                .map(|v| v.unwrap().path())
+                .filter(|v| v.is_dir())
+                .collect::<Vec<_>>();
+            ns_dirs.sort();
+            for ns_dir in ns_dirs {
+                let name_server = ns_dir.file_name().unwrap().to_str().unwrap().to_owned();
+                let mut chunks = read_dir(&ns_dir)
+                    .unwrap()
+                    .map(|v| v.unwrap().path())
+                    .filter(|v| v.extension().and_then(|v| v.to_str()) == Some("txt"))
+                    .collect::<Vec<_>>();
+                chunks.sort();
+                for path in chunks {
+                    let file = BufReader::new(File::open(&path).unwrap());
+                    let (_, all_domains) = container::read::<AllDomains>(file)
+                        .unwrap_or_else(|e| panic!("cannot read {}: {:?}", path.display(), e));
+                    f(&name_server, record_type.as_deref(), &all_domains);
+                }
+                logs.push((
+                    name_server,
+                    record_type.clone(),
+                    ns_dir.join("observations.log"),
+                ));
+            }
+        }
+    }
+    for (name_server, record_type, log) in logs {
+        if log.is_file() {
+            let mut all_domains = AllDomains::new();
+            aggregate(&read_log(&log).unwrap(), &mut all_domains);
+            f(&name_server, record_type.as_deref(), &all_domains);
+        }
+    }
+}
+
+/// Writes the records of every name server in `source` to `output`, or to stdout without one,
+/// one row per record and chunk. The format defaults to the one named by the extension of
+/// `output`, else JSON Lines.
+fn export(format: Option<ExportFormat>, source: &Path, output: Option<&Path>) {
+    let format = format
+        .or_else(|| output.and_then(ExportFormat::from_path))
+        .unwrap_or(ExportFormat::JsonLines);
+    let mut export = match output {
+        Some(output) => {
+            let file = File::create(output).expect("Error creating export file");
+            exporter(format, BufWriter::new(file))
+        }
+        None => exporter(format, BufWriter::new(std::io::stdout())),
+    }
+    .unwrap();
+    for_each_chunk(source, |name_server, record_type, all_domains| {
+        export
+            .write(&rows(name_server, record_type, all_domains))
+            .unwrap();
+    });
+    export.close().unwrap();
+}
+
+pub fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 && args[1] == "export" {
+        let mut rest = &args[2..];
+        let mut format = None;
+        if rest.len() > 1 && rest[0] == "--format" {
+            format = Some(rest[1].parse().expect("Unknown export format"));
+            rest = &rest[2..];
+        }
+        match rest {
+            [source] => export(format, Path::new(source), None),
+            [source, output] => export(format, Path::new(source), Some(Path::new(output))),
+            _ => {
+                print_usage(&args[0]);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if args.len() != 3 {
+        print_usage(&args[0]);
+        std::process::exit(1);
+    }
+    for_each_results(Path::new(&args[1]), |record_type, results| {
+        if let Some(record_type) = record_type {
+            println!("##### {} #####", record_type);
+        }
+        print_report(results, &args[2]);
+    });
 }
@@ -1,17 +1,25 @@
+use crate::ecs::ClientSubnet;
 use crate::error::*;
+use crate::message::build_query;
 use crate::name_server::NameServer;
+use crate::observation::{Observation, ObservationLog};
+use crate::rate_limit::RateLimiter;
 use crate::record_wrapper::RecordWrapper;
+use crate::retry::RetryPolicy;
+use crate::transport::{self, Response, Transport};
+use futures::stream::{self, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::io::Write;
-use std::net::IpAddr;
-use std::process::Command;
 use std::str::FromStr;
-use trust_dns_proto::rr::rdata::NULL;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use trust_dns_proto::op::{Message, ResponseCode};
+use trust_dns_proto::rr::dnssec::rdata::DNSSECRData;
 use trust_dns_proto::rr::{Name, RData, Record, RecordType};
 
-const ERROR_LOG_NAME: &str = "error_log.txt";
+pub(crate) const ERROR_LOG_NAME: &str = "error_log.txt";
 
 pub type AllDomains = HashMap<Name, HashMap<RecordWrapper, DomainStat>>;
 
@@ -19,17 +27,198 @@ pub type AllDomains = HashMap<Name, HashMap<RecordWrapper, DomainStat>>;
 pub struct DomainStat {
     pub counts: usize,
     pub ttls: HashSet<u32>,
+    /// Every sighting of the record, in the order the answers came in.
+    pub observations: Vec<TtlObservation>,
+    /// The TTL the zone gives the record, known when an RRSIG covering it was in an answer.
+    pub original_ttl: Option<u32>,
+}
+
+/// The TTL a record had left when an answer carried it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TtlObservation {
+    pub observed_at: SystemTime,
+    pub ttl: u32,
+}
+
+/// Insertion times closer than this are taken for the same cache fill, as TTLs only count
+/// whole seconds and the answer takes a while to arrive.
+const FILL_TOLERANCE: Duration = Duration::from_secs(2);
+
+impl DomainStat {
+    /// When the record entered the cache according to each observation, counting down from
+    /// `original_ttl`: observed time − (original TTL − remaining TTL). Observations above
+    /// `original_ttl`, from a resolver stretching TTLs, give no estimate.
+    pub fn insertion_times(&self, original_ttl: u32) -> Vec<SystemTime> {
+        self.observations
+            .iter()
+            .filter(|v| v.ttl <= original_ttl)
+            .filter_map(|v| {
+                v.observed_at
+                    .checked_sub(Duration::from_secs((original_ttl - v.ttl).into()))
+            })
+            .collect()
+    }
+
+    /// The distinct cache fills behind the observations, oldest first.
+    pub fn cache_fills(&self, original_ttl: u32) -> Vec<SystemTime> {
+        let mut times = self.insertion_times(original_ttl);
+        times.sort_unstable();
+        let mut fills = Vec::<SystemTime>::new();
+        for time in times {
+            match fills.last() {
+                Some(last) if time.duration_since(*last).unwrap_or_default() <= FILL_TOLERANCE => {}
+                _ => fills.push(time),
+            }
+        }
+        fills
+    }
 }
 
 impl std::fmt::Debug for DomainStat {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         formatter.write_str(&format!(
-            "DomainStat {{counts: {:?}, ttls: {:?}}}",
-            self.counts, self.ttls
+            "DomainStat {{counts: {:?}, ttls: {:?}, observations: {}, original_ttl: {:?}}}",
+            self.counts,
+            self.ttls,
+            self.observations.len(),
+            self.original_ttl
         ))
     }
 }
 
+/// Original TTL of every RRset a baseline name server answered with, by owner name and type
+/// code.
+pub type Baseline = HashMap<(Name, u16), u32>;
+
+/// Per queried name: how the name server answered the probes for it.
+pub type AllQueries = HashMap<Name, QueryStat>;
+
+/// The header of a response, reduced to what tells "not cached" apart from "not answered".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct QueryOutcome {
+    pub response_code: u16,
+    pub authoritative: bool,
+    pub recursion_available: bool,
+    /// Set when the response came back truncated, even if the TCP retry then got all of it.
+    pub truncated: bool,
+    pub recursion_desired: bool,
+    pub answer_count: usize,
+    pub authority_count: usize,
+    /// Scope prefix length of the EDNS Client Subnet option in the response, if there was one.
+    pub client_subnet_scope: Option<u8>,
+}
+
+impl QueryOutcome {
+    pub fn new(response: &Response) -> Self {
+        let message = &response.message;
+        Self {
+            response_code: message.response_code().into(),
+            authoritative: message.authoritative(),
+            recursion_available: message.recursion_available(),
+            truncated: response.tcp_fallback || message.truncated(),
+            recursion_desired: message.recursion_desired(),
+            answer_count: message.answers().len(),
+            authority_count: message.name_servers().len(),
+            client_subnet_scope: ClientSubnet::from_message(message).map(|(_, scope)| scope),
+        }
+    }
+
+    pub fn response_code(&self) -> ResponseCode {
+        self.response_code.into()
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueryStat {
+    /// How often each distinct outcome was seen over the repeats.
+    pub outcomes: HashMap<QueryOutcome, usize>,
+    /// Round-trip time of every answered probe, in the order the answers came in.
+    pub latencies: Vec<Duration>,
+    /// Outcome of the recursive query sent after the probes, in differential mode.
+    pub control: Option<QueryOutcome>,
+    /// How often each CNAME chain was seen over the repeats, as the owner names from the queried
+    /// name to the one holding the terminal records. The records themselves are in `AllDomains`.
+    pub chains: HashMap<Vec<Name>, usize>,
+}
+
+impl QueryStat {
+    /// Whether the name resolved when asked recursively although no probe got an answer, i.e.
+    /// the probes could not see the name or it was not cached.
+    pub fn missed_by_snooping(&self) -> bool {
+        self.control.is_some_and(|control| control.answer_count > 0)
+            && self
+                .outcomes
+                .keys()
+                .all(|outcome| outcome.answer_count == 0)
+    }
+
+    /// Number of CNAMEs in the longest chain seen, 0 if the answers never went through one.
+    pub fn chain_depth(&self) -> usize {
+        self.chains
+            .keys()
+            .map(|chain| chain.len() - 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn latency(&self) -> Option<Latency> {
+        let mut latencies = self.latencies.clone();
+        latencies.sort_unstable();
+        // nearest rank
+        let percentile = |p: usize| latencies[(latencies.len() * p).div_ceil(100).max(1) - 1];
+        Some(Latency {
+            min: *latencies.first()?,
+            median: percentile(50),
+            p95: percentile(95),
+        })
+    }
+}
+
+/// The original TTLs (RFC 4034 3.1.4) of the RRsets in `answers` that came with an RRSIG, by
+/// owner name and type.
+pub(crate) fn original_ttls(answers: &[Record]) -> HashMap<(Name, RecordType), u32> {
+    answers
+        .iter()
+        .filter_map(|record| match record.rdata() {
+            RData::DNSSEC(DNSSECRData::SIG(sig)) => Some((
+                (record.name().clone(), sig.type_covered()),
+                sig.original_ttl(),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Follows the CNAMEs in `answers` from `query_name`, returning the names passed through, or
+/// `None` if the answers do not start with a CNAME for `query_name`.
+pub fn cname_chain(query_name: &Name, answers: &[Record]) -> Option<Vec<Name>> {
+    let mut chain = vec![query_name.clone()];
+    loop {
+        let owner = chain.last().unwrap();
+        let target = answers.iter().find_map(|record| match record.rdata() {
+            RData::CNAME(target) if record.name() == owner => Some(target),
+            _ => None,
+        });
+        match target {
+            // a loop ends the chain where it closes
+            Some(target) if !chain.contains(target) => chain.push(target.clone()),
+            _ => break,
+        }
+    }
+    if chain.len() > 1 {
+        Some(chain)
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Latency {
+    pub min: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct CollectMetadata {
     pub in_queries: usize,
@@ -38,160 +227,431 @@ pub struct CollectMetadata {
     pub repeat_valid: usize,
     pub response_count: usize,
     pub response_valid: usize,
+    pub tcp_fallbacks: usize,
+    /// Total time queries were held back by the rate limit, summed over queries.
+    pub throttled: Duration,
+    /// Queries not sent because the query budget was spent.
+    pub budget_exhausted: usize,
+    /// Attempts that got no response within the timeout, including ones that were retried.
+    pub timeouts: usize,
+    /// Attempts sent again after a timeout or error.
+    pub retries: usize,
+    /// Queries that still failed after their last retry.
+    pub give_ups: usize,
+    /// Recursive control queries answered, in differential mode.
+    pub controls: usize,
+    /// Names whose control query resolved but whose probes all came back empty.
+    pub snooping_misses: usize,
 }
 
-pub fn collect(
+pub async fn collect(
     name_server: &NameServer,
     domain_names: &[String],
     record_type: RecordType,
     repeat: usize,
     all_domains_counts: &mut AllDomains,
+    all_queries: &mut AllQueries,
 ) -> CollectMetadata {
-    let domain_membership_test = domain_names
-        .iter()
-        .map(|v| Name::from_str(v))
-        .filter(|v| v.is_ok())
-        .map(|v| {
-            let mut v = v.unwrap();
-            v.set_fqdn(true);
-            v
-        })
-        .collect::<HashSet<Name>>();
-    let mut error_log = OpenOptions::new().append(true).open(ERROR_LOG_NAME);
-    let mut meta = CollectMetadata::default();
-    for _ in 0..repeat {
-        meta.repeat_count += 1;
-        let response = query(&name_server.host, domain_names, record_type);
-        if let Ok(response) = response {
-            meta.repeat_valid += 1;
-            meta.response_count += response.len();
-            response
-                .into_iter()
-                .filter(|v| {
-                    if v.is_err() {
-                        if let Ok(error_log) = error_log.as_mut() {
-                            let _ = writeln!(
-                                error_log,
-                                "{}: {:?}",
-                                chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                                v
-                            );
-                        }
-                    }
-                    v.is_ok()
-                })
-                .map(|v| v.unwrap())
-                .for_each(|v| {
-                    meta.response_valid += 1;
-                    let name = v.name().clone();
-                    if domain_membership_test.contains(&name) {
-                        meta.in_queries += 1;
-                    } else {
-                        meta.not_in_queries += 1;
-                    }
-                    let ttl = v.ttl();
-                    let record_counts = all_domains_counts.entry(name).or_insert_with(HashMap::new);
-                    let stat = record_counts
-                        .entry(RecordWrapper::new(v))
-                        .or_insert_with(DomainStat::default);
-                    stat.counts += 1;
-                    stat.ttls.insert(ttl);
-                });
-        } else if let Ok(error_log) = error_log.as_mut() {
-            let _ = writeln!(
-                error_log,
-                "{}: {:?}",
-                chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-                response
-            );
+    match Collector::new(name_server) {
+        Ok(collector) => {
+            collector
+                .collect(
+                    domain_names,
+                    record_type,
+                    repeat,
+                    all_domains_counts,
+                    all_queries,
+                )
+                .await
+        }
+        Err(e) => {
+            let mut error_log = OpenOptions::new().append(true).open(ERROR_LOG_NAME);
+            log_error(&mut error_log, &e);
+            CollectMetadata {
+                repeat_count: repeat,
+                ..CollectMetadata::default()
+            }
         }
     }
-    meta
 }
 
-pub fn query(
-    name_server: &str,
-    domain_names: &[String],
-    record_type: RecordType,
-) -> Result<Vec<Result<Record, RecordParseError>>, QueryError> {
-    let domain_names_result = domain_names
-        .iter()
-        .map(|name| Name::from_str(name).map_err(QueryError::TrustDnsProtoError))
-        .collect::<Vec<_>>();
-    let mut domain_names = Vec::new();
-    for name in domain_names_result.into_iter() {
-        domain_names.push(name?.to_utf8());
-    }
-
-    let output = Command::new("dig")
-        .args(&["+noall", "+answer", "+norecurse"])
-        .arg(format!("@{}", name_server))
-        .args(domain_names.as_slice())
-        .arg(format!("{}", record_type))
-        .output()
-        .map_err(QueryError::CommandError)?;
-    let result = String::from_utf8(output.stdout).map_err(QueryError::StringConvertError)?;
-    let records_result = result
-        .lines()
-        .map(|l| {
-            let line = l.split_ascii_whitespace().collect::<Vec<_>>();
-            if line.len() != 5 {
-                Err(RecordParseError::NotEnoughArguments)
-            } else {
-                let domain_name =
-                    Name::from_str(&line[0]).map_err(RecordParseError::InvalidDomainName)?;
-                let ttl = line[1]
-                    .parse::<u32>()
-                    .map_err(RecordParseError::InvalidTtl)?;
-                let record_type: RecordType =
-                    FromStr::from_str(&line[3]).map_err(RecordParseError::InvalidRecord)?;
-                let rdata = parse_record_data(&line[4], record_type)
-                    .map_err(RecordParseError::InvalidRData)?;
-                let record = Record::from_rdata(domain_name, ttl, rdata);
-                Ok(record)
-            }
-        })
-        .collect::<Vec<_>>();
-    Ok(records_result)
+/// Collection state for one name server that outlives a single `collect` call, e.g. the query
+/// budget of a run.
+pub struct Collector {
+    transport: Arc<dyn Transport>,
+    rate_limiter: RateLimiter,
+    retry_policy: RetryPolicy,
+    max_in_flight: usize,
+    differential: bool,
+    client_subnet: Option<ClientSubnet>,
+    observation_log: Option<Mutex<ObservationLog>>,
 }
 
-pub fn parse_record_data(rdata: &str, record_type: RecordType) -> Result<RData, RDataParseError> {
-    match record_type {
-        RecordType::A | RecordType::AAAA => {
-            let ip_addr = IpAddr::from_str(rdata).map_err(RDataParseError::InvalidIpAddr)?;
-            Ok(match ip_addr {
-                IpAddr::V4(ip) => RData::A(ip),
-                IpAddr::V6(ip) => RData::AAAA(ip),
-            })
+/// What it took to get one query answered, or to give up on it.
+struct Attempts {
+    throttled: Duration,
+    timeouts: usize,
+    retries: usize,
+    /// Round-trip time of the last attempt, including a TCP retry after truncation.
+    latency: Duration,
+    result: Result<Response, QueryError>,
+}
+
+impl Collector {
+    pub fn new(name_server: &NameServer) -> Result<Self, QueryError> {
+        let mut collector = Self::with_transport(
+            transport::from_name_server(name_server)?.into(),
+            name_server.max_in_flight,
+        );
+        collector.rate_limiter = RateLimiter::new(name_server.rate_limit, name_server.query_budget);
+        collector.retry_policy = name_server.retry;
+        collector.differential = name_server.differential;
+        Ok(collector)
+    }
+
+    /// Sends the queries through an arbitrary `transport`, keeping at most `max_in_flight` of
+    /// them outstanding at a time, without any rate limit and with the default retry policy.
+    pub fn with_transport(transport: Arc<dyn Transport>, max_in_flight: usize) -> Self {
+        Self {
+            transport,
+            rate_limiter: RateLimiter::unlimited(),
+            retry_policy: RetryPolicy::default(),
+            max_in_flight,
+            differential: false,
+            client_subnet: None,
+            observation_log: None,
         }
-        RecordType::ANAME => {
-            Ok(RData::ANAME(Name::from_str(rdata).map_err(|e| {
-                RDataParseError::InvalidName(record_type, e)
-            })?))
+    }
+
+    pub fn set_rate_limiter(&mut self, rate_limiter: RateLimiter) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// In differential mode every name also gets one recursive (RD=1) control query once all
+    /// repeats are done.
+    pub fn set_differential(&mut self, differential: bool) {
+        self.differential = differential;
+    }
+
+    /// Sends every query, including control queries, with an EDNS Client Subnet option for
+    /// `client_subnet`.
+    pub fn set_client_subnet(&mut self, client_subnet: Option<ClientSubnet>) {
+        self.client_subnet = client_subnet;
+    }
+
+    /// Appends every probe response to `observation_log`, one entry per answer record.
+    pub fn set_observation_log(&mut self, observation_log: Option<ObservationLog>) {
+        self.observation_log = observation_log.map(Mutex::new);
+    }
+
+    fn log_observations(&self, request: &Message, response: &Response, latency: Duration) {
+        let (log, query) = match (&self.observation_log, request.queries().first()) {
+            (Some(log), Some(query)) => (log, query),
+            _ => return,
+        };
+        let mut log = log.lock().unwrap();
+        let observation = Observation {
+            observed_at: SystemTime::now(),
+            name_server: log.name_server().to_owned(),
+            client_subnet: self.client_subnet.map(|v| v.to_string()),
+            query_name: query.name().clone(),
+            query_type: query.query_type().into(),
+            response_code: response.message.response_code().into(),
+            latency,
+            record: None,
+        };
+        let answers = response.message.answers();
+        let observations = if answers.is_empty() {
+            vec![observation]
+        } else {
+            answers
+                .iter()
+                .map(|v| Observation {
+                    record: Some(RecordWrapper::new(v.clone())),
+                    ..observation.clone()
+                })
+                .collect()
+        };
+        if let Err(e) = log.append(&observations) {
+            let mut error_log = OpenOptions::new().append(true).open(ERROR_LOG_NAME);
+            log_error(&mut error_log, &e);
         }
-        RecordType::CNAME => {
-            Ok(RData::CNAME(Name::from_str(rdata).map_err(|e| {
-                RDataParseError::InvalidName(record_type, e)
-            })?))
+    }
+
+    fn build_query(&self, name: Name, record_type: RecordType) -> Message {
+        let mut request = build_query(name, record_type);
+        if let Some(client_subnet) = self.client_subnet {
+            client_subnet.apply(&mut request);
         }
-        RecordType::NS => {
-            Ok(RData::NS(Name::from_str(rdata).map_err(|e| {
-                RDataParseError::InvalidName(record_type, e)
-            })?))
+        request
+    }
+
+    /// Sends `request` until it is answered or the retries run out. Every attempt goes through
+    /// the rate limiter and counts against the query budget.
+    async fn query(&self, request: &Message) -> Attempts {
+        let mut attempts = Attempts {
+            throttled: Duration::default(),
+            timeouts: 0,
+            retries: 0,
+            latency: Duration::default(),
+            result: Err(QueryError::BudgetExhausted),
+        };
+        for retry in 0..=self.retry_policy.retries {
+            if retry > 0 {
+                tokio::time::sleep(self.retry_policy.backoff(retry)).await;
+                attempts.retries += 1;
+            }
+            match self.rate_limiter.acquire().await {
+                Some(throttled) => attempts.throttled += throttled,
+                None => {
+                    attempts.result = Err(QueryError::BudgetExhausted);
+                    break;
+                }
+            }
+            let start = Instant::now();
+            attempts.result =
+                tokio::time::timeout(self.retry_policy.timeout(), self.transport.query(request))
+                    .await
+                    .unwrap_or(Err(QueryError::Timeout));
+            attempts.latency = start.elapsed();
+            if let Ok(response) = &attempts.result {
+                attempts.latency = attempts.latency.saturating_sub(response.setup);
+            }
+            match attempts.result {
+                Ok(_) => break,
+                Err(QueryError::Timeout) => attempts.timeouts += 1,
+                Err(_) => {}
+            }
         }
-        RecordType::PTR => {
-            Ok(RData::PTR(Name::from_str(rdata).map_err(|e| {
-                RDataParseError::InvalidName(record_type, e)
-            })?))
+        attempts
+    }
+
+    /// Each repeat is a full pass over `domain_names` and the next one only starts after it is
+    /// done. Records are filed under their owner names in `all_domains_counts`, response headers
+    /// and CNAME chains under the queried name in `all_queries`.
+    pub async fn collect(
+        &self,
+        domain_names: &[String],
+        record_type: RecordType,
+        repeat: usize,
+        all_domains_counts: &mut AllDomains,
+        all_queries: &mut AllQueries,
+    ) -> CollectMetadata {
+        let mut error_log = OpenOptions::new().append(true).open(ERROR_LOG_NAME);
+        let mut query_names = Vec::new();
+        for domain_name in domain_names {
+            match Name::from_str(domain_name) {
+                Ok(mut name) => {
+                    name.set_fqdn(true);
+                    query_names.push(name);
+                }
+                Err(e) => log_error(&mut error_log, &QueryError::TrustDnsProtoError(e)),
+            }
         }
-        RecordType::NULL => Ok(RData::NULL(NULL::with(rdata.as_bytes().to_owned()))),
-        RecordType::Unknown(code) => Ok(RData::Unknown {
-            code,
-            rdata: NULL::with(rdata.as_bytes().to_owned()),
-        }),
-        _ => Ok(RData::Unknown {
-            code: record_type.into(),
-            rdata: NULL::with(rdata.as_bytes().to_owned()),
-        }),
+        let domain_membership_test = query_names.iter().cloned().collect::<HashSet<Name>>();
+        let mut meta = CollectMetadata::default();
+        for _ in 0..repeat {
+            meta.repeat_count += 1;
+            let mut repeat_valid = true;
+            let requests = query_names
+                .iter()
+                .map(|query_name| self.build_query(query_name.clone(), record_type))
+                .collect::<Vec<_>>();
+            let mut responses = self.query_all(requests);
+            while let Some((request, attempts)) = responses.next().await {
+                let latency = attempts.latency;
+                let response = match meta.account(attempts) {
+                    Some(response) => response,
+                    None => {
+                        repeat_valid = false;
+                        continue;
+                    }
+                };
+                match response {
+                    Ok(mut response) => {
+                        if response.tcp_fallback {
+                            meta.tcp_fallbacks += 1;
+                        }
+                        self.log_observations(&request, &response, latency);
+                        if let Some(query) = request.queries().first() {
+                            let stat = all_queries.entry(query.name().clone()).or_default();
+                            *stat
+                                .outcomes
+                                .entry(QueryOutcome::new(&response))
+                                .or_default() += 1;
+                            stat.latencies.push(latency);
+                            if let Some(chain) =
+                                cname_chain(query.name(), response.message.answers())
+                            {
+                                *stat.chains.entry(chain).or_default() += 1;
+                            }
+                        }
+                        let observed_at = SystemTime::now();
+                        let original_ttls = original_ttls(response.message.answers());
+                        let answers = response.message.take_answers();
+                        meta.response_count += answers.len();
+                        answers.into_iter().for_each(|v| {
+                            meta.response_valid += 1;
+                            let name = v.name().clone();
+                            if domain_membership_test.contains(&name) {
+                                meta.in_queries += 1;
+                            } else {
+                                meta.not_in_queries += 1;
+                            }
+                            let ttl = v.ttl();
+                            let original_ttl =
+                                original_ttls.get(&(name.clone(), v.rr_type())).cloned();
+                            let record_counts = all_domains_counts.entry(name).or_default();
+                            let stat = record_counts.entry(RecordWrapper::new(v)).or_default();
+                            stat.counts += 1;
+                            stat.ttls.insert(ttl);
+                            stat.observations.push(TtlObservation { observed_at, ttl });
+                            if original_ttl.is_some() {
+                                stat.original_ttl = original_ttl;
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        repeat_valid = false;
+                        log_error(&mut error_log, &e);
+                    }
+                }
+            }
+            if repeat_valid {
+                meta.repeat_valid += 1;
+            }
+        }
+        if self.differential {
+            // only after all repeats, as the recursive queries fill the cache being snooped on
+            let requests = query_names
+                .iter()
+                .map(|query_name| {
+                    let mut request = self.build_query(query_name.clone(), record_type);
+                    request.set_recursion_desired(true);
+                    request
+                })
+                .collect::<Vec<_>>();
+            let mut responses = self.query_all(requests);
+            while let Some((request, attempts)) = responses.next().await {
+                match meta.account(attempts) {
+                    Some(Ok(response)) => {
+                        meta.controls += 1;
+                        if let Some(query) = request.queries().first() {
+                            let stat = all_queries.entry(query.name().clone()).or_default();
+                            stat.control = Some(QueryOutcome::new(&response));
+                            if stat.missed_by_snooping() {
+                                meta.snooping_misses += 1;
+                            }
+                        }
+                    }
+                    Some(Err(e)) => log_error(&mut error_log, &e),
+                    None => {}
+                }
+            }
+        }
+        meta
+    }
+
+    /// Asks for every name once with recursion desired and keeps the TTLs of the answers, which
+    /// are the original ones when the name server is authoritative or does not cache.
+    pub async fn collect_baseline(
+        &self,
+        domain_names: &[String],
+        record_type: RecordType,
+        baseline: &mut Baseline,
+    ) -> CollectMetadata {
+        let mut error_log = OpenOptions::new().append(true).open(ERROR_LOG_NAME);
+        let mut meta = CollectMetadata {
+            repeat_count: 1,
+            repeat_valid: 1,
+            ..CollectMetadata::default()
+        };
+        let mut requests = Vec::new();
+        for domain_name in domain_names {
+            match Name::from_str(domain_name) {
+                Ok(mut name) => {
+                    name.set_fqdn(true);
+                    let mut request = self.build_query(name, record_type);
+                    request.set_recursion_desired(true);
+                    requests.push(request);
+                }
+                Err(e) => log_error(&mut error_log, &QueryError::TrustDnsProtoError(e)),
+            }
+        }
+        let mut responses = self.query_all(requests);
+        while let Some((_, attempts)) = responses.next().await {
+            match meta.account(attempts) {
+                Some(Ok(response)) => {
+                    meta.response_count += response.message.answers().len();
+                    for record in response.message.answers() {
+                        meta.response_valid += 1;
+                        let ttl = baseline
+                            .entry((record.name().clone(), record.rr_type().into()))
+                            .or_default();
+                        *ttl = (*ttl).max(record.ttl());
+                    }
+                }
+                Some(Err(e)) => {
+                    meta.repeat_valid = 0;
+                    log_error(&mut error_log, &e);
+                }
+                None => meta.repeat_valid = 0,
+            }
+        }
+        meta
+    }
+
+    /// Sends all `requests`, at most `max_in_flight` at a time, yielding them back with their
+    /// attempts as they complete.
+    fn query_all(
+        &self,
+        requests: Vec<Message>,
+    ) -> impl Stream<Item = (Message, Attempts)> + Unpin + '_ {
+        stream::iter(requests)
+            .map(move |request| async move {
+                let attempts = self.query(&request).await;
+                (request, attempts)
+            })
+            .buffer_unordered(self.max_in_flight.max(1))
+    }
+}
+
+impl CollectMetadata {
+    /// Books what `attempts` cost and hands back their result, or `None` if the query was never
+    /// sent because the budget ran out.
+    fn account(&mut self, attempts: Attempts) -> Option<Result<Response, QueryError>> {
+        self.throttled += attempts.throttled;
+        self.timeouts += attempts.timeouts;
+        self.retries += attempts.retries;
+        match attempts.result {
+            Err(QueryError::BudgetExhausted) => {
+                self.budget_exhausted += 1;
+                None
+            }
+            Err(e) => {
+                self.give_ups += 1;
+                Some(Err(e))
+            }
+            response => Some(response),
+        }
+    }
+}
+
+pub(crate) fn log_error(error_log: &mut std::io::Result<File>, error: &impl std::fmt::Debug) {
+    if let Ok(error_log) = error_log.as_mut() {
+        let _ = writeln!(
+            error_log,
+            "{}: {:?}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            error
+        );
     }
 }
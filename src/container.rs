@@ -0,0 +1,238 @@
+//! Self-describing file format for collection results.
+//!
+//! A file is the magic `DNSC`, the format version (u16), the length of the header (u32) and the
+//! bincode header, the length of the body (u64) and the bincode body, and finally a CRC-32 of all
+//! of the above. Integers in the framing are big-endian. Files from before this format are a bare
+//! bincode body and read as version 0.
+
+use crate::collect::{AllDomains, AllQueries, Baseline, DomainStat};
+use crate::error::*;
+use crate::record_wrapper::RecordWrapper;
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::time::SystemTime;
+use trust_dns_proto::rr::Name;
+
+pub const MAGIC: [u8; 4] = *b"DNSC";
+pub const FORMAT_VERSION: u16 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Content {
+    AllDomains,
+    AllQueries,
+    Baseline,
+}
+
+/// What a file holds and how it was collected.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    /// Version of this crate that wrote the file.
+    pub crate_version: String,
+    pub content: Content,
+    pub record_type: String,
+    /// Name of the name server, with the client subnet for ECS slices.
+    pub name_server: String,
+    pub client_subnet: Option<String>,
+    pub repeat: usize,
+    /// Ranks of the first and last name of the chunk in the top list, counting from 1.
+    pub first_rank: usize,
+    pub last_rank: usize,
+    pub differential: bool,
+    pub started_at: SystemTime,
+    pub finished_at: SystemTime,
+}
+
+impl Header {
+    pub fn new(content: Content) -> Self {
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_owned(),
+            content,
+            record_type: String::new(),
+            name_server: String::new(),
+            client_subnet: None,
+            repeat: 0,
+            first_rank: 0,
+            last_rank: 0,
+            differential: false,
+            started_at: SystemTime::UNIX_EPOCH,
+            finished_at: SystemTime::UNIX_EPOCH,
+        }
+    }
+}
+
+/// A type that can be the body of a file.
+pub trait Body: Serialize + DeserializeOwned {
+    const CONTENT: Content;
+
+    /// Decodes a bare file written before the container format in a layout the type no longer
+    /// has.
+    fn from_legacy(_bytes: &[u8]) -> Option<Self> {
+        None
+    }
+}
+
+impl Body for AllDomains {
+    const CONTENT: Content = Content::AllDomains;
+
+    /// `DomainStat` used to be just the counts and TTLs.
+    fn from_legacy(bytes: &[u8]) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct DomainStatV0 {
+            counts: usize,
+            ttls: HashSet<u32>,
+        }
+        let legacy: HashMap<Name, HashMap<RecordWrapper, DomainStatV0>> = decode(bytes).ok()?;
+        Some(
+            legacy
+                .into_iter()
+                .map(|(name, records)| {
+                    let records = records
+                        .into_iter()
+                        .map(|(record, stat)| {
+                            let stat = DomainStat {
+                                counts: stat.counts,
+                                ttls: stat.ttls,
+                                ..DomainStat::default()
+                            };
+                            (record, stat)
+                        })
+                        .collect();
+                    (name, records)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Body for AllQueries {
+    const CONTENT: Content = Content::AllQueries;
+}
+
+impl Body for Baseline {
+    const CONTENT: Content = Content::Baseline;
+}
+
+/// The bincode settings of `bincode::serialize`, refusing trailing bytes so that a body in
+/// another layout does not pass for this one.
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, ContainerError> {
+    options()
+        .with_limit(bytes.len() as u64)
+        .deserialize(bytes)
+        .map_err(ContainerError::Encoding)
+}
+
+pub fn write<T: Body>(
+    mut writer: impl Write,
+    header: &Header,
+    body: &T,
+) -> Result<(), ContainerError> {
+    let header = options()
+        .serialize(header)
+        .map_err(ContainerError::Encoding)?;
+    let body = options()
+        .serialize(body)
+        .map_err(ContainerError::Encoding)?;
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+    bytes.extend_from_slice(&(header.len() as u32).to_be_bytes());
+    bytes.extend(header);
+    bytes.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    bytes.extend(body);
+    let checksum = crc32(&bytes);
+    bytes.extend_from_slice(&checksum.to_be_bytes());
+    writer.write_all(&bytes).map_err(ContainerError::Io)
+}
+
+/// Reads a file in any known version, returning its header, which files from before the
+/// container format do not have.
+pub fn read<T: Body>(mut reader: impl Read) -> Result<(Option<Header>, T), ContainerError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(ContainerError::Io)?;
+    if !bytes.starts_with(&MAGIC) {
+        return match decode(&bytes) {
+            Ok(body) => Ok((None, body)),
+            Err(e) => T::from_legacy(&bytes).map(|v| (None, v)).ok_or(e),
+        };
+    }
+    let (header, body) = unframe(&bytes)?;
+    if header.content != T::CONTENT {
+        return Err(ContainerError::WrongContent(header.content));
+    }
+    Ok((Some(header), decode(body)?))
+}
+
+/// Reads and verifies a file without decoding its body, returning `None` for a file from before
+/// the container format.
+pub fn read_header(mut reader: impl Read) -> Result<Option<Header>, ContainerError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(ContainerError::Io)?;
+    if !bytes.starts_with(&MAGIC) {
+        return Ok(None);
+    }
+    unframe(&bytes).map(|(header, _)| Some(header))
+}
+
+/// Checks the version and checksum of a file and splits it into its header and encoded body.
+fn unframe(bytes: &[u8]) -> Result<(Header, &[u8]), ContainerError> {
+    if bytes.len() < MAGIC.len() + 4 {
+        return Err(ContainerError::Truncated);
+    }
+    let (framed, checksum) = bytes.split_at(bytes.len() - 4);
+    let mut fields = Fields(&framed[MAGIC.len()..]);
+    let version = u16::from_be_bytes(fields.take_array()?);
+    if version != FORMAT_VERSION {
+        return Err(ContainerError::UnsupportedVersion(version));
+    }
+    if crc32(framed).to_be_bytes() != checksum {
+        return Err(ContainerError::BadChecksum);
+    }
+    let header_len = u32::from_be_bytes(fields.take_array()?) as usize;
+    let header = decode(fields.take(header_len)?)?;
+    let body_len = u64::from_be_bytes(fields.take_array()?) as usize;
+    Ok((header, fields.take(body_len)?))
+}
+
+/// The framing fields of a file, consumed front to back.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ContainerError> {
+        if self.0.len() < len {
+            return Err(ContainerError::Truncated);
+        }
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(field)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], ContainerError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+}
+
+/// CRC-32 as in zlib and PNG (reflected polynomial 0xEDB88320).
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
@@ -0,0 +1,237 @@
+use crate::collect::{log_error, ERROR_LOG_NAME};
+use crate::ecs::ClientSubnet;
+use crate::error::*;
+use crate::presentation::{self, parse_name, Token};
+use crate::rdata::{parse_record_data, parse_record_type, record_type_name};
+use crate::transport::{Response, Transport};
+use async_trait::async_trait;
+use std::fs::OpenOptions;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::process::Command;
+use trust_dns_proto::op::{Message, MessageType, ResponseCode};
+use trust_dns_proto::rr::Record;
+
+/// Legacy backend that shells out to `dig` and parses its presentation-format output.
+///
+/// The answer and authority sections, the response code and the header flags are recovered;
+/// the additional section and EDNS options are not.
+pub struct DigTransport {
+    name_server: SocketAddr,
+}
+
+impl DigTransport {
+    pub fn new(name_server: SocketAddr) -> Self {
+        Self { name_server }
+    }
+}
+
+#[async_trait]
+impl Transport for DigTransport {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError> {
+        // make sure a timed out dig does not linger
+        let output = Command::new("dig")
+            .kill_on_drop(true)
+            .args(command_args(self.name_server, request))
+            .output()
+            .await
+            .map_err(QueryError::CommandError)?;
+        // dig exits with 9 when no server answered
+        if output.status.code() == Some(9) {
+            return Err(QueryError::Timeout);
+        }
+        let result = String::from_utf8(output.stdout).map_err(QueryError::StringConvertError)?;
+        let mut message = Message::new();
+        message
+            .set_id(request.id())
+            .set_message_type(MessageType::Response)
+            .add_queries(request.queries().to_vec());
+        let skipped = parse_output(&result, &mut message)?;
+        if !skipped.is_empty() {
+            let mut error_log = OpenOptions::new().append(true).open(ERROR_LOG_NAME);
+            for e in skipped.iter() {
+                log_error(&mut error_log, e);
+            }
+        }
+        Ok(Response {
+            message,
+            tcp_fallback: false,
+            setup: Duration::default(),
+        })
+    }
+}
+
+/// The arguments that make `dig` ask `name_server` what `request` asks, with the same RD and DO
+/// bits and client subnet.
+pub fn command_args(name_server: SocketAddr, request: &Message) -> Vec<String> {
+    // retries and timeouts are up to the caller
+    let mut args = ["+noall", "+comments", "+answer", "+authority", "+tries=1"]
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    args.push(if request.recursion_desired() {
+        "+recurse".to_owned()
+    } else {
+        "+norecurse".to_owned()
+    });
+    args.push(if request.edns().is_some_and(|edns| edns.dnssec_ok()) {
+        "+dnssec".to_owned()
+    } else {
+        "+nodnssec".to_owned()
+    });
+    if let Some((client_subnet, _)) = ClientSubnet::from_message(request) {
+        args.push(format!("+subnet={}", client_subnet));
+    }
+    args.push(format!("@{}", name_server.ip()));
+    args.push("-p".to_owned());
+    args.push(name_server.port().to_string());
+    for query in request.queries() {
+        args.push(query.name().to_utf8());
+        args.push(record_type_name(query.query_type()));
+    }
+    args
+}
+
+/// Fills `message` from the output of `dig +noall +comments +answer +authority`.
+///
+/// Records may span several lines, as with `+multiline`. A record that cannot be parsed is left
+/// out and its error returned, so that the rest of the answer is kept.
+pub fn parse_output(
+    output: &str,
+    message: &mut Message,
+) -> Result<Vec<RecordParseError>, QueryError> {
+    let mut skipped = Vec::new();
+    let mut header_seen = false;
+    let mut in_authority = false;
+    // a record whose parentheses or quotes are still open
+    let mut pending = String::new();
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if !pending.is_empty() {
+            pending.push('\n');
+            pending.push_str(line);
+        } else if let Some(header) = trimmed.strip_prefix(";; ->>HEADER<<-") {
+            parse_status(header, message)?;
+            header_seen = true;
+            continue;
+        } else if let Some(flags) = trimmed.strip_prefix(";; flags:") {
+            parse_flags(flags, message);
+            continue;
+        } else if trimmed.starts_with(";; ANSWER SECTION:") {
+            in_authority = false;
+            continue;
+        } else if trimmed.starts_with(";; AUTHORITY SECTION:") {
+            in_authority = true;
+            continue;
+        } else if trimmed.is_empty() || trimmed.starts_with(';') {
+            continue;
+        } else {
+            pending.push_str(line);
+        }
+        let lines = match presentation::tokenize(&pending) {
+            Err(TokenizeError::Unterminated(_)) => continue,
+            Err(e) => {
+                skipped.push(RecordParseError::InvalidSyntax(e));
+                pending.clear();
+                continue;
+            }
+            Ok(lines) => lines,
+        };
+        pending.clear();
+        for tokens in lines {
+            match parse_tokens(&tokens) {
+                Ok(record) if in_authority => {
+                    message.add_name_server(record);
+                }
+                Ok(record) => {
+                    message.add_answer(record);
+                }
+                Err(e) => skipped.push(e),
+            }
+        }
+    }
+    if !pending.is_empty() {
+        return Err(QueryError::UnexpectedDigOutput(pending));
+    }
+    if header_seen {
+        Ok(skipped)
+    } else {
+        Err(QueryError::UnexpectedDigOutput(output.to_owned()))
+    }
+}
+
+/// Parses the rest of `;; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4711`.
+fn parse_status(header: &str, message: &mut Message) -> Result<(), QueryError> {
+    let status = header
+        .split(',')
+        .filter_map(|field| field.trim().strip_prefix("status:"))
+        .map(str::trim)
+        .next()
+        .ok_or_else(|| QueryError::UnexpectedDigOutput(header.to_owned()))?;
+    let response_code = match status {
+        "NOERROR" => ResponseCode::NoError,
+        "FORMERR" => ResponseCode::FormErr,
+        "SERVFAIL" => ResponseCode::ServFail,
+        "NXDOMAIN" => ResponseCode::NXDomain,
+        "NOTIMP" => ResponseCode::NotImp,
+        "REFUSED" => ResponseCode::Refused,
+        "YXDOMAIN" => ResponseCode::YXDomain,
+        "YXRRSET" => ResponseCode::YXRRSet,
+        "NXRRSET" => ResponseCode::NXRRSet,
+        "NOTAUTH" => ResponseCode::NotAuth,
+        "NOTZONE" => ResponseCode::NotZone,
+        "BADVERS" => ResponseCode::BADVERS,
+        // codes dig has no name for are printed as e.g. RESERVED11
+        _ => match status.strip_prefix("RESERVED").map(u16::from_str) {
+            Some(Ok(code)) => code.into(),
+            _ => return Err(QueryError::UnexpectedDigOutput(header.to_owned())),
+        },
+    };
+    message.set_response_code(response_code);
+    Ok(())
+}
+
+/// Parses the rest of `;; flags: qr aa rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 1`.
+fn parse_flags(flags: &str, message: &mut Message) {
+    let flags = flags.split(';').next().unwrap_or_default();
+    for flag in flags.split_ascii_whitespace() {
+        match flag {
+            "aa" => message.set_authoritative(true),
+            "tc" => message.set_truncated(true),
+            "rd" => message.set_recursion_desired(true),
+            "ra" => message.set_recursion_available(true),
+            "ad" => message.set_authentic_data(true),
+            "cd" => message.set_checking_disabled(true),
+            _ => message,
+        };
+    }
+}
+
+/// Parses one record in presentation format, e.g. `example.com. 300 IN TXT "a b" c`.
+pub fn parse_answer_line(line: &str) -> Result<Record, RecordParseError> {
+    let mut lines = presentation::tokenize(line).map_err(RecordParseError::InvalidSyntax)?;
+    if lines.len() != 1 {
+        return Err(RecordParseError::NotEnoughArguments);
+    }
+    parse_tokens(&lines.remove(0))
+}
+
+/// Owner, TTL, class and type, followed by the RDATA tokens.
+fn parse_tokens(tokens: &[Token]) -> Result<Record, RecordParseError> {
+    if tokens.len() < 4 {
+        return Err(RecordParseError::NotEnoughArguments);
+    }
+    let line = tokens.iter().map(|v| v.text.as_str()).collect::<Vec<_>>();
+    let domain_name = parse_name(line[0]).map_err(RecordParseError::InvalidDomainName)?;
+    let ttl = line[1]
+        .parse::<u32>()
+        .map_err(RecordParseError::InvalidTtl)?;
+    let record_type = parse_record_type(line[3]).map_err(RecordParseError::InvalidRData)?;
+    let rdata =
+        parse_record_data(&line[4..], record_type).map_err(RecordParseError::InvalidRData)?;
+    let mut record = Record::from_rdata(domain_name, ttl, rdata);
+    // an RRSIG would otherwise be typed SIG after its RDATA
+    record.set_rr_type(record_type);
+    Ok(record)
+}
@@ -0,0 +1,129 @@
+use crate::error::*;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use trust_dns_proto::op::Message;
+use trust_dns_proto::rr::rdata::opt::{EdnsCode, EdnsOption};
+
+/// EDNS Client Subnet option code (RFC 7871).
+const OPTION_CODE: u16 = 8;
+
+/// A client subnet such as `192.0.2.0/24`, sent as an EDNS Client Subnet option (RFC 7871).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ClientSubnet {
+    addr: IpAddr,
+    source_prefix: u8,
+}
+
+impl ClientSubnet {
+    /// Fails if `source_prefix` is longer than the address; bits past the prefix are cleared.
+    pub fn new(addr: IpAddr, source_prefix: u8) -> Result<Self, ClientSubnetParseError> {
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        if source_prefix > max_prefix {
+            return Err(ClientSubnetParseError::InvalidPrefixLength(
+                source_prefix.to_string(),
+            ));
+        }
+        let addr = match addr {
+            IpAddr::V4(ip) => {
+                let mut octets = ip.octets();
+                mask(&mut octets, source_prefix);
+                IpAddr::from(octets)
+            }
+            IpAddr::V6(ip) => {
+                let mut octets = ip.octets();
+                mask(&mut octets, source_prefix);
+                IpAddr::from(octets)
+            }
+        };
+        Ok(Self {
+            addr,
+            source_prefix,
+        })
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn source_prefix(&self) -> u8 {
+        self.source_prefix
+    }
+
+    /// Encodes the option with a scope prefix of 0, as a query must.
+    pub fn to_option(&self) -> EdnsOption {
+        let (family, octets) = match self.addr {
+            IpAddr::V4(ip) => (1u16, ip.octets().to_vec()),
+            IpAddr::V6(ip) => (2u16, ip.octets().to_vec()),
+        };
+        let mut data = family.to_be_bytes().to_vec();
+        data.push(self.source_prefix);
+        data.push(0);
+        data.extend_from_slice(&octets[..(self.source_prefix as usize).div_ceil(8)]);
+        EdnsOption::Unknown(OPTION_CODE, data)
+    }
+
+    /// Attaches the option to `message`, which must already carry an EDNS OPT record.
+    pub fn apply(&self, message: &mut Message) {
+        message.edns_mut().set_option(self.to_option());
+    }
+
+    /// Returns the subnet and scope prefix of the option in `message`, if any.
+    pub fn from_message(message: &Message) -> Option<(Self, u8)> {
+        match message.edns()?.option(EdnsCode::Subnet)? {
+            EdnsOption::Unknown(_, data) => Self::decode(data),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    fn decode(data: &[u8]) -> Option<(Self, u8)> {
+        let family = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+        let source_prefix = *data.get(2)?;
+        let scope_prefix = *data.get(3)?;
+        let addr = &data[4..];
+        let addr = match family {
+            1 if addr.len() <= 4 => {
+                let mut octets = [0u8; 4];
+                octets[..addr.len()].copy_from_slice(addr);
+                IpAddr::from(octets)
+            }
+            2 if addr.len() <= 16 => {
+                let mut octets = [0u8; 16];
+                octets[..addr.len()].copy_from_slice(addr);
+                IpAddr::from(octets)
+            }
+            _ => return None,
+        };
+        Some((Self::new(addr, source_prefix).ok()?, scope_prefix))
+    }
+}
+
+impl FromStr for ClientSubnet {
+    type Err = ClientSubnetParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = s
+            .split_once('/')
+            .ok_or_else(|| ClientSubnetParseError::MissingPrefixLength(s.to_owned()))?;
+        let addr = IpAddr::from_str(addr).map_err(ClientSubnetParseError::InvalidIpAddr)?;
+        let prefix = prefix
+            .parse::<u8>()
+            .map_err(|_| ClientSubnetParseError::InvalidPrefixLength(prefix.to_owned()))?;
+        Self::new(addr, prefix)
+    }
+}
+
+impl fmt::Display for ClientSubnet {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}/{}", self.addr, self.source_prefix)
+    }
+}
+
+/// Clears every bit of `octets` past the first `prefix` ones.
+fn mask(octets: &mut [u8], prefix: u8) {
+    for (i, byte) in octets.iter_mut().enumerate() {
+        let bits = (prefix as u32).saturating_sub(i as u32 * 8).min(8);
+        *byte &= !0xffu8.checked_shr(bits).unwrap_or(0);
+    }
+}
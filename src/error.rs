@@ -5,10 +5,27 @@ pub enum RDataParseError {
         trust_dns_proto::rr::RecordType,
         trust_dns_proto::error::ProtoError,
     ),
+    InvalidType(String),
+    MissingField(trust_dns_proto::rr::RecordType),
+    InvalidField(trust_dns_proto::rr::RecordType, String),
+    InvalidWireData(
+        trust_dns_proto::rr::RecordType,
+        trust_dns_proto::error::ProtoError,
+    ),
+}
+
+#[derive(Debug)]
+pub enum TokenizeError {
+    /// A quoted string or parenthesized group still open at the end of the input.
+    Unterminated(usize),
+    UnbalancedParenthesis(usize),
+    DanglingEscape(usize),
+    InvalidEscape(String),
 }
 
 #[derive(Debug)]
 pub enum RecordParseError {
+    InvalidSyntax(TokenizeError),
     NotEnoughArguments,
     InvalidDomainName(trust_dns_proto::error::ProtoError),
     InvalidTtl(std::num::ParseIntError),
@@ -16,10 +33,68 @@ pub enum RecordParseError {
     InvalidRData(RDataParseError),
 }
 
+#[derive(Debug)]
+pub enum ClientSubnetParseError {
+    MissingPrefixLength(String),
+    InvalidIpAddr(std::net::AddrParseError),
+    InvalidPrefixLength(String),
+}
+
+#[derive(Debug)]
+pub enum ContainerError {
+    Io(std::io::Error),
+    Encoding(bincode::Error),
+    Truncated,
+    BadChecksum,
+    UnsupportedVersion(u16),
+    WrongContent(crate::container::Content),
+}
+
+#[derive(Debug)]
+pub enum ResumeError {
+    Io(std::io::Error),
+    InvalidManifest(serde_json::Error),
+    MissingManifest,
+    /// The crawl was started with a different value of this parameter.
+    Mismatch(&'static str),
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Sqlite(rusqlite::Error),
+    InvalidContent(String),
+    InvalidName(String, trust_dns_proto::error::ProtoError),
+    InvalidType(String),
+    InvalidRecord(trust_dns_proto::error::ProtoError),
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    UnknownFormat(String),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    Arrow(arrow_schema::ArrowError),
+    Parquet(parquet::errors::ParquetError),
+}
+
 #[derive(Debug)]
 pub enum QueryError {
     CommandError(std::io::Error),
     StringConvertError(std::string::FromUtf8Error),
     TrustDnsProtoError(trust_dns_proto::error::ProtoError),
     RecordParseError(RecordParseError),
+    InvalidNameServer(std::net::AddrParseError),
+    IoError(std::io::Error),
+    MessageTooLong(usize),
+    TlsError(rustls::Error),
+    InvalidTlsServerName(rustls::pki_types::InvalidDnsNameError),
+    InvalidCertificate(rustls::pki_types::pem::Error),
+    HttpError(reqwest::Error),
+    HttpStatus(u16),
+    MismatchedResponse,
+    InvalidUrl(String),
+    Timeout,
+    BudgetExhausted,
+    UnexpectedDigOutput(String),
 }
@@ -0,0 +1,292 @@
+//! Flat exports of collected records, one row per record and name server, for tools that do not
+//! speak bincode. The individual TTL observations are left out.
+
+use crate::collect::{AllDomains, DomainStat};
+use crate::error::*;
+use crate::presentation::format_name;
+use crate::rdata::{format_record_data, record_type_name};
+use crate::record_wrapper::RecordWrapper;
+use arrow_array::builder::{ListBuilder, UInt32Builder};
+use arrow_array::{ArrayRef, RecordBatch, StringArray, UInt32Array, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use trust_dns_proto::rr::Name;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RecordRow {
+    pub name_server: String,
+    /// Type of the queries the record was an answer to, unknown for crawls from before record
+    /// type directories.
+    pub query_type: Option<String>,
+    pub name: String,
+    pub record_type: String,
+    pub class: String,
+    /// RDATA in presentation form.
+    pub rdata: String,
+    pub counts: usize,
+    pub min_ttl: Option<u32>,
+    pub max_ttl: Option<u32>,
+    pub original_ttl: Option<u32>,
+    /// The distinct TTLs seen, in ascending order.
+    pub ttls: Vec<u32>,
+}
+
+impl RecordRow {
+    pub fn new(
+        name_server: &str,
+        query_type: Option<&str>,
+        name: &Name,
+        record: &RecordWrapper,
+        stat: &DomainStat,
+    ) -> Self {
+        let record = record.record();
+        let mut ttls = stat.ttls.iter().cloned().collect::<Vec<_>>();
+        ttls.sort_unstable();
+        Self {
+            name_server: name_server.to_owned(),
+            query_type: query_type.map(ToOwned::to_owned),
+            name: format_name(name),
+            record_type: record_type_name(record.record_type()),
+            class: record.dns_class().to_string(),
+            rdata: format_record_data(record.rdata(), record.record_type()),
+            counts: stat.counts,
+            min_ttl: ttls.first().cloned(),
+            max_ttl: ttls.last().cloned(),
+            original_ttl: stat.original_ttl,
+            ttls,
+        }
+    }
+}
+
+/// The rows of every record in `all_domains`, ordered by name, type and RDATA so that exports
+/// of the same results compare equal.
+pub fn rows(
+    name_server: &str,
+    query_type: Option<&str>,
+    all_domains: &AllDomains,
+) -> Vec<RecordRow> {
+    let mut rows = all_domains
+        .iter()
+        .flat_map(|(name, records)| {
+            records.iter().map(move |(record, stat)| {
+                RecordRow::new(name_server, query_type, name, record, stat)
+            })
+        })
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| {
+        (&a.name, &a.record_type, &a.rdata).cmp(&(&b.name, &b.record_type, &b.rdata))
+    });
+    rows
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Parquet,
+    JsonLines,
+    Csv,
+}
+
+impl ExportFormat {
+    /// The format an output file name asks for by its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "parquet" => Ok(ExportFormat::Parquet),
+            "jsonl" | "json" => Ok(ExportFormat::JsonLines),
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(ExportError::UnknownFormat(s.to_owned())),
+        }
+    }
+}
+
+pub trait Export {
+    fn write(&mut self, rows: &[RecordRow]) -> Result<(), ExportError>;
+
+    /// Finishes the output, which may not be readable before.
+    fn close(self: Box<Self>) -> Result<(), ExportError>;
+}
+
+pub fn exporter<W: Write + Send + 'static>(
+    format: ExportFormat,
+    writer: W,
+) -> Result<Box<dyn Export>, ExportError> {
+    Ok(match format {
+        ExportFormat::Parquet => Box::new(ParquetExport::new(writer)?),
+        ExportFormat::JsonLines => Box::new(JsonLinesExport::new(writer)),
+        ExportFormat::Csv => Box::new(CsvExport::new(writer)?),
+    })
+}
+
+/// One JSON object per line.
+pub struct JsonLinesExport<W: Write>(W);
+
+impl<W: Write> JsonLinesExport<W> {
+    pub fn new(writer: W) -> Self {
+        Self(writer)
+    }
+}
+
+impl<W: Write> Export for JsonLinesExport<W> {
+    fn write(&mut self, rows: &[RecordRow]) -> Result<(), ExportError> {
+        for row in rows {
+            serde_json::to_writer(&mut self.0, row).map_err(ExportError::Json)?;
+            self.0.write_all(b"\n").map_err(ExportError::Io)?;
+        }
+        self.0.flush().map_err(ExportError::Io)
+    }
+
+    fn close(mut self: Box<Self>) -> Result<(), ExportError> {
+        self.0.flush().map_err(ExportError::Io)
+    }
+}
+
+/// CSV with a header line, the TTLs of a record separated by spaces.
+pub struct CsvExport<W: Write>(csv::Writer<W>);
+
+impl<W: Write> CsvExport<W> {
+    pub fn new(writer: W) -> Result<Self, ExportError> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer
+            .write_record([
+                "name_server",
+                "query_type",
+                "name",
+                "record_type",
+                "class",
+                "rdata",
+                "counts",
+                "min_ttl",
+                "max_ttl",
+                "original_ttl",
+                "ttls",
+            ])
+            .map_err(ExportError::Csv)?;
+        Ok(Self(writer))
+    }
+}
+
+impl<W: Write> Export for CsvExport<W> {
+    fn write(&mut self, rows: &[RecordRow]) -> Result<(), ExportError> {
+        let optional = |v: Option<u32>| v.map(|v| v.to_string()).unwrap_or_default();
+        for row in rows {
+            let ttls = row
+                .ttls
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.0
+                .write_record([
+                    row.name_server.clone(),
+                    row.query_type.clone().unwrap_or_default(),
+                    row.name.clone(),
+                    row.record_type.clone(),
+                    row.class.clone(),
+                    row.rdata.clone(),
+                    row.counts.to_string(),
+                    optional(row.min_ttl),
+                    optional(row.max_ttl),
+                    optional(row.original_ttl),
+                    ttls,
+                ])
+                .map_err(ExportError::Csv)?;
+        }
+        self.0.flush().map_err(ExportError::Io)
+    }
+
+    fn close(mut self: Box<Self>) -> Result<(), ExportError> {
+        self.0.flush().map_err(ExportError::Io)
+    }
+}
+
+/// Parquet, one row group per call to `write`.
+pub struct ParquetExport<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+}
+
+impl<W: Write + Send> ParquetExport<W> {
+    pub fn new(writer: W) -> Result<Self, ExportError> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("name_server", DataType::Utf8, false),
+            Field::new("query_type", DataType::Utf8, true),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("record_type", DataType::Utf8, false),
+            Field::new("class", DataType::Utf8, false),
+            Field::new("rdata", DataType::Utf8, false),
+            Field::new("counts", DataType::UInt64, false),
+            Field::new("min_ttl", DataType::UInt32, true),
+            Field::new("max_ttl", DataType::UInt32, true),
+            Field::new("original_ttl", DataType::UInt32, true),
+            Field::new(
+                "ttls",
+                DataType::List(Arc::new(Field::new("item", DataType::UInt32, true))),
+                false,
+            ),
+        ]));
+        let writer =
+            ArrowWriter::try_new(writer, schema.clone(), None).map_err(ExportError::Parquet)?;
+        Ok(Self { writer, schema })
+    }
+}
+
+impl<W: Write + Send> Export for ParquetExport<W> {
+    fn write(&mut self, rows: &[RecordRow]) -> Result<(), ExportError> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let strings = |f: fn(&RecordRow) -> &str| -> ArrayRef {
+            Arc::new(rows.iter().map(f).map(Some).collect::<StringArray>())
+        };
+        let mut ttls = ListBuilder::new(UInt32Builder::new());
+        for row in rows {
+            ttls.values().append_slice(&row.ttls);
+            ttls.append(true);
+        }
+        let columns: Vec<ArrayRef> = vec![
+            strings(|v| &v.name_server),
+            Arc::new(
+                rows.iter()
+                    .map(|v| v.query_type.as_deref())
+                    .collect::<StringArray>(),
+            ),
+            strings(|v| &v.name),
+            strings(|v| &v.record_type),
+            strings(|v| &v.class),
+            strings(|v| &v.rdata),
+            Arc::new(
+                rows.iter()
+                    .map(|v| v.counts as u64)
+                    .collect::<UInt64Array>(),
+            ),
+            Arc::new(rows.iter().map(|v| v.min_ttl).collect::<UInt32Array>()),
+            Arc::new(rows.iter().map(|v| v.max_ttl).collect::<UInt32Array>()),
+            Arc::new(rows.iter().map(|v| v.original_ttl).collect::<UInt32Array>()),
+            Arc::new(ttls.finish()),
+        ];
+        let batch =
+            RecordBatch::try_new(self.schema.clone(), columns).map_err(ExportError::Arrow)?;
+        self.writer.write(&batch).map_err(ExportError::Parquet)?;
+        self.writer.flush().map_err(ExportError::Parquet)
+    }
+
+    /// Writes the file footer, without which the file cannot be read.
+    fn close(self: Box<Self>) -> Result<(), ExportError> {
+        self.writer
+            .close()
+            .map(|_| ())
+            .map_err(ExportError::Parquet)
+    }
+}
@@ -0,0 +1,107 @@
+use crate::error::*;
+use crate::transport::{Response, Transport};
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::Client;
+use rustls::ClientConfig;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::Duration;
+use trust_dns_proto::op::Message;
+
+pub const DEFAULT_PORT: u16 = 443;
+pub const DNS_MESSAGE: &str = "application/dns-message";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    #[default]
+    Post,
+    Get,
+}
+
+pub struct HttpsTransport {
+    client: Client,
+    url: String,
+    method: HttpMethod,
+}
+
+impl HttpsTransport {
+    pub fn new(client: Client, url: String, method: HttpMethod) -> Self {
+        Self {
+            client,
+            url,
+            method,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for HttpsTransport {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError> {
+        Ok(Response {
+            message: exchange(&self.client, &self.url, self.method, request).await?,
+            tcp_fallback: false,
+            setup: Duration::default(),
+        })
+    }
+}
+
+/// Builds an HTTP client for DNS-over-HTTPS. HTTP/2 is negotiated through ALPN.
+///
+/// If `pin` is given, the host of the query URL is resolved to it instead of going through the
+/// system resolver, so that the configured resolver address is the one being snooped.
+pub fn client(
+    tls_config: &ClientConfig,
+    pin: Option<(&str, SocketAddr)>,
+) -> Result<Client, QueryError> {
+    let mut tls_config = tls_config.clone();
+    tls_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    let mut builder = Client::builder().use_preconfigured_tls(tls_config);
+    if let Some((domain, addr)) = pin {
+        builder = builder.resolve(domain, addr);
+    }
+    builder.build().map_err(QueryError::HttpError)
+}
+
+/// Sends `request` as an RFC 8484 `application/dns-message` to `url`.
+///
+/// `url` may be given as an RFC 6570 template ending in `{?dns}`, as resolvers usually
+/// publish it.
+pub async fn exchange(
+    client: &Client,
+    url: &str,
+    method: HttpMethod,
+    request: &Message,
+) -> Result<Message, QueryError> {
+    let url = url.trim_end_matches("{?dns}");
+    // RFC 8484 4.1: use ID 0 so that responses are cache friendly
+    let mut request = request.clone();
+    request.set_id(0);
+    let bytes = request.to_vec().map_err(QueryError::TrustDnsProtoError)?;
+    let http_request = match method {
+        HttpMethod::Post => client
+            .post(url)
+            .header(CONTENT_TYPE, DNS_MESSAGE)
+            .body(bytes),
+        HttpMethod::Get => client
+            .get(url)
+            .query(&[("dns", URL_SAFE_NO_PAD.encode(&bytes))]),
+    };
+    let http_response = http_request
+        .header(ACCEPT, DNS_MESSAGE)
+        .send()
+        .await
+        .map_err(QueryError::HttpError)?;
+    if !http_response.status().is_success() {
+        return Err(QueryError::HttpStatus(http_response.status().as_u16()));
+    }
+    let body = http_response.bytes().await.map_err(QueryError::HttpError)?;
+    let response = Message::from_vec(&body).map_err(QueryError::TrustDnsProtoError)?;
+    if response.id() != request.id() || response.queries() != request.queries() {
+        return Err(QueryError::MismatchedResponse);
+    }
+    Ok(response)
+}
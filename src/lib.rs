@@ -1,4 +1,21 @@
 pub mod collect;
+pub mod container;
+pub mod dig;
+pub mod ecs;
 pub mod error;
+pub mod export;
+pub mod https;
+pub mod message;
 pub mod name_server;
+pub mod observation;
+pub mod presentation;
+pub mod rate_limit;
+pub mod rdata;
 pub mod record_wrapper;
+pub mod resume;
+pub mod retry;
+pub mod store;
+pub mod tcp;
+pub mod tls;
+pub mod transport;
+pub mod udp;
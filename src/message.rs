@@ -0,0 +1,22 @@
+use trust_dns_proto::op::{Edns, Message, MessageType, OpCode, Query};
+use trust_dns_proto::rr::{Name, RecordType};
+
+/// Advertised EDNS payload size, following the DNS Flag Day 2020 recommendation.
+pub const EDNS_PAYLOAD: u16 = 1232;
+
+/// Builds a non-recursive (RD=0) query for `name`, with the DO bit set so that RRSIGs and the
+/// original TTLs in them come back along with the answers.
+pub fn build_query(name: Name, record_type: RecordType) -> Message {
+    let mut edns = Edns::new();
+    edns.set_max_payload(EDNS_PAYLOAD);
+    edns.set_dnssec_ok(true);
+    let mut message = Message::new();
+    message
+        .set_id(rand::random())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(false)
+        .add_query(Query::query(name, record_type))
+        .set_edns(edns);
+    message
+}
@@ -1,11 +1,92 @@
+use crate::https::{self, HttpMethod};
+use crate::rate_limit::RateLimit;
+use crate::retry::RetryPolicy;
+use crate::{tls, udp};
 use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::path::Path;
+use std::net::{AddrParseError, IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Udp,
+    Tcp,
+    Dig,
+    Tls,
+    Https,
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NameServer {
     pub name: String,
     pub host: String,
+    /// Defaults to the well-known port of `transport`.
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub transport: TransportKind,
+    /// Name checked against the resolver's TLS certificate; defaults to `host`.
+    #[serde(default)]
+    pub tls_server_name: Option<String>,
+    /// Extra PEM certificates to trust besides the webpki roots.
+    #[serde(default)]
+    pub tls_ca_file: Option<PathBuf>,
+    /// DNS-over-HTTPS endpoint, e.g. `https://dns.google/dns-query{?dns}`; defaults to
+    /// `https://<host>/dns-query`.
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub http_method: HttpMethod,
+    /// Upper bound on the number of queries awaiting a response at any time.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    /// Total number of queries this name server may receive in one run.
+    #[serde(default)]
+    pub query_budget: Option<usize>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Follow the non-recursive probes with a recursive control query per name.
+    #[serde(default)]
+    pub differential: bool,
+    /// Query each name only once, recursively, for the original TTLs the other name servers are
+    /// compared with. Point it at an authoritative server or a resolver that does not cache.
+    #[serde(default)]
+    pub baseline: bool,
+}
+
+fn default_max_in_flight() -> usize {
+    32
+}
+
+impl NameServer {
+    pub fn socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
+        let ip = IpAddr::from_str(&self.host)?;
+        let port = self.port.unwrap_or(match self.transport {
+            TransportKind::Udp | TransportKind::Tcp | TransportKind::Dig => udp::DEFAULT_PORT,
+            TransportKind::Tls => tls::DEFAULT_PORT,
+            TransportKind::Https => https::DEFAULT_PORT,
+        });
+        Ok(SocketAddr::new(ip, port))
+    }
+
+    pub fn https_url(&self) -> String {
+        match &self.url {
+            Some(url) => url.clone(),
+            None => match IpAddr::from_str(&self.host) {
+                Ok(IpAddr::V6(ip)) => format!("https://[{}]/dns-query", ip),
+                _ => format!("https://{}/dns-query", self.host),
+            },
+        }
+    }
+
+    pub fn tls_server_name(&self) -> &str {
+        self.tls_server_name.as_deref().unwrap_or(&self.host)
+    }
 }
 
 pub fn parse_name_servers_json(path: &Path) -> Vec<NameServer> {
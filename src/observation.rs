@@ -0,0 +1,147 @@
+//! Append-only log of every answer as it came in, from which `AllDomains` can be derived.
+
+use crate::collect::{original_ttls, AllDomains, TtlObservation};
+use crate::record_wrapper::RecordWrapper;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use trust_dns_proto::rr::Name;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Observation {
+    pub observed_at: SystemTime,
+    pub name_server: String,
+    /// The client subnet sent along with the query, as `Header::client_subnet` gives it.
+    pub client_subnet: Option<String>,
+    pub query_name: Name,
+    pub query_type: u16,
+    pub response_code: u16,
+    pub latency: Duration,
+    /// One answer record with the TTL it had left, or `None` for a response without answers, so
+    /// that misses are logged as well.
+    pub record: Option<RecordWrapper>,
+}
+
+/// Writes observations to the end of a file, each as a 4-byte big-endian length followed by its
+/// bincode encoding, so that a crash can only cut off the last entry.
+pub struct ObservationLog {
+    file: BufWriter<File>,
+    name_server: String,
+}
+
+impl ObservationLog {
+    /// Opens `path` for appending, creating it if needed. Entries are attributed to
+    /// `name_server`.
+    pub fn open(path: &Path, name_server: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: BufWriter::new(file),
+            name_server: name_server.to_owned(),
+        })
+    }
+
+    pub fn name_server(&self) -> &str {
+        &self.name_server
+    }
+
+    pub fn append(&mut self, observations: &[Observation]) -> std::io::Result<()> {
+        for observation in observations {
+            let bytes = bincode::serialize(observation)
+                .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+            self.file.write_all(&(bytes.len() as u32).to_be_bytes())?;
+            self.file.write_all(&bytes)?;
+        }
+        self.file.flush()
+    }
+}
+
+/// Reads back every complete entry of the log at `path`, ignoring a trailing one cut short.
+pub fn read_log(path: &Path) -> std::io::Result<Vec<Observation>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut observations = Vec::new();
+    loop {
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let mut bytes = vec![0u8; u32::from_be_bytes(len) as usize];
+        match reader.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let observation = bincode::deserialize(&bytes)
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+        observations.push(observation);
+    }
+    Ok(observations)
+}
+
+/// Drops the entries of the log at `path` observed after `cutoff`, along with a trailing one cut
+/// short, returning how many complete ones were dropped. A missing log is left missing.
+pub fn truncate_log(path: &Path, cutoff: SystemTime) -> std::io::Result<usize> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let observations = read_log(path)?;
+    let kept = observations
+        .iter()
+        .filter(|v| v.observed_at <= cutoff)
+        .cloned()
+        .collect::<Vec<_>>();
+    let partial = path.with_extension("log.partial");
+    let mut log = ObservationLog {
+        file: BufWriter::new(File::create(&partial)?),
+        name_server: String::new(),
+    };
+    log.append(&kept)?;
+    std::fs::rename(&partial, path)?;
+    Ok(observations.len() - kept.len())
+}
+
+/// Files the answer records of `observations` under their owner names, as `collect` does.
+pub fn aggregate(observations: &[Observation], all_domains: &mut AllDomains) {
+    // the records of one response are logged one after another, with the same time
+    let responses = observations.chunk_by(|a, b| {
+        a.observed_at == b.observed_at
+            && a.name_server == b.name_server
+            && a.client_subnet == b.client_subnet
+            && a.query_name == b.query_name
+            && a.query_type == b.query_type
+    });
+    for response in responses {
+        let answers = response
+            .iter()
+            .filter_map(|v| Some(v.record.as_ref()?.record().clone()))
+            .collect::<Vec<_>>();
+        let original_ttls = original_ttls(&answers);
+        for (observation, record) in response
+            .iter()
+            .filter_map(|v| Some((v, v.record.as_ref()?)))
+        {
+            let name = record.record().name().clone();
+            let ttl = record.record().ttl();
+            let original_ttl = original_ttls
+                .get(&(name.clone(), record.record().rr_type()))
+                .cloned();
+            let stat = all_domains
+                .entry(name)
+                .or_default()
+                .entry(record.clone())
+                .or_default();
+            stat.counts += 1;
+            stat.ttls.insert(ttl);
+            stat.observations.push(TtlObservation {
+                observed_at: observation.observed_at,
+                ttl,
+            });
+            if original_ttl.is_some() {
+                stat.original_ttl = original_ttl;
+            }
+        }
+    }
+}
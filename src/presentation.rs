@@ -0,0 +1,215 @@
+//! Tokenizer for the master file presentation format (RFC 1035 5.1) that dig prints.
+
+use crate::error::*;
+use trust_dns_proto::error::ProtoResult;
+use trust_dns_proto::rr::domain::Label;
+use trust_dns_proto::rr::Name;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Token {
+    /// The token with its quotes removed but its escapes (`\X`, `\DDD`) still in place, so that a
+    /// domain name can tell an escaped dot from a label separator.
+    pub text: String,
+    /// Whether any part of the token was quoted.
+    pub quoted: bool,
+}
+
+/// Splits `input` into logical lines of tokens.
+///
+/// Parentheses continue a line over line breaks, `;` starts a comment running to the end of the
+/// physical line, and whitespace, parentheses and `;` lose their meaning inside quotes or after a
+/// backslash.
+pub fn tokenize(input: &str) -> Result<Vec<Vec<Token>>, TokenizeError> {
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    let mut token: Option<Token> = None;
+    let mut in_quotes = false;
+    let mut depth = 0usize;
+    let mut line_number = 1;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or(TokenizeError::DanglingEscape(line_number))?;
+                let token = token.get_or_insert_with(Token::default);
+                token.text.push('\\');
+                token.text.push(escaped);
+                if escaped == '\n' {
+                    line_number += 1;
+                }
+            }
+            '"' => {
+                in_quotes = !in_quotes;
+                token.get_or_insert_with(Token::default).quoted = true;
+            }
+            _ if in_quotes => {
+                if c == '\n' {
+                    line_number += 1;
+                }
+                token.get_or_insert_with(Token::default).text.push(c);
+            }
+            ';' => {
+                line.extend(token.take());
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                end_line(&mut lines, &mut line, depth);
+                line_number += 1;
+            }
+            '(' => {
+                line.extend(token.take());
+                depth += 1;
+            }
+            ')' => {
+                line.extend(token.take());
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or(TokenizeError::UnbalancedParenthesis(line_number))?;
+            }
+            '\n' => {
+                line.extend(token.take());
+                end_line(&mut lines, &mut line, depth);
+                line_number += 1;
+            }
+            _ if c.is_whitespace() => line.extend(token.take()),
+            _ => token.get_or_insert_with(Token::default).text.push(c),
+        }
+    }
+    if in_quotes || depth > 0 {
+        return Err(TokenizeError::Unterminated(line_number));
+    }
+    line.extend(token.take());
+    end_line(&mut lines, &mut line, depth);
+    Ok(lines)
+}
+
+fn end_line(lines: &mut Vec<Vec<Token>>, line: &mut Vec<Token>, depth: usize) {
+    if depth == 0 && !line.is_empty() {
+        lines.push(std::mem::take(line));
+    }
+}
+
+/// Resolves the `\X` and `\DDD` escapes of a token into the bytes they stand for.
+pub fn unescape(text: &str) -> Result<Vec<u8>, TokenizeError> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('\\') {
+        bytes.extend_from_slice(&rest.as_bytes()[..i]);
+        rest = &rest[i + 1..];
+        let digits = rest.bytes().take(3).take_while(u8::is_ascii_digit).count();
+        if digits == 3 {
+            let byte = rest[..3]
+                .parse::<u8>()
+                .map_err(|_| TokenizeError::InvalidEscape(text.to_owned()))?;
+            bytes.push(byte);
+            rest = &rest[3..];
+        } else {
+            let c = rest
+                .chars()
+                .next()
+                .ok_or_else(|| TokenizeError::InvalidEscape(text.to_owned()))?;
+            bytes.extend_from_slice(&rest.as_bytes()[..c.len_utf8()]);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    bytes.extend_from_slice(rest.as_bytes());
+    Ok(bytes)
+}
+
+/// Splits `text` on the occurrences of `separator` that are not escaped.
+pub fn split_unescaped(text: &str, separator: char) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if c == separator => {
+                items.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    items.push(&text[start..]);
+    items
+}
+
+/// Parses a domain name token, keeping the label bytes exactly as escaped.
+///
+/// `Name::from_str` reads `\DDD` as octal and lowercases through IDNA, so dig's `\032` would not
+/// match the name a decoded response carries.
+pub fn parse_name(text: &str) -> ProtoResult<Name> {
+    if text == "." {
+        return Ok(Name::root());
+    }
+    let mut items = split_unescaped(text, '.');
+    // a trailing unescaped dot leaves an empty last item
+    let fqdn = items.len() > 1 && items.last() == Some(&"");
+    if fqdn {
+        items.pop();
+    }
+    let mut labels = Vec::new();
+    for label in items {
+        let bytes = unescape(label).map_err(|e| format!("{:?}", e))?;
+        if bytes.is_empty() {
+            return Err(format!("empty label in {}", text).into());
+        }
+        labels.push(Label::from_raw_bytes(&bytes)?);
+    }
+    let mut name = Name::from_labels(labels)?;
+    name.set_fqdn(fqdn);
+    Ok(name)
+}
+
+/// Writes a domain name the way `parse_name` reads it, escaping dots and the characters the
+/// tokenizer gives a meaning to, and everything unprintable as `\DDD`.
+pub fn format_name(name: &Name) -> String {
+    if name.is_root() {
+        return ".".to_owned();
+    }
+    let mut text = String::new();
+    for (i, label) in name.iter().enumerate() {
+        if i > 0 {
+            text.push('.');
+        }
+        escape_into(&mut text, label, b".\\\"();", false);
+    }
+    if name.is_fqdn() {
+        text.push('.');
+    }
+    text
+}
+
+/// Writes `bytes` as a quoted character string (RFC 1035 5.1).
+pub fn format_character_string(bytes: &[u8]) -> String {
+    let mut text = String::from("\"");
+    escape_into(&mut text, bytes, b"\\\"", true);
+    text.push('"');
+    text
+}
+
+/// Writes `bytes` unquoted, so that `unescape` gives them back.
+pub fn escape(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    escape_into(&mut text, bytes, b"\\\"();", false);
+    text
+}
+
+fn escape_into(text: &mut String, bytes: &[u8], special: &[u8], quoted: bool) {
+    for &byte in bytes {
+        if special.contains(&byte) {
+            text.push('\\');
+            text.push(char::from(byte));
+        } else if byte.is_ascii_graphic() || (quoted && byte == b' ') {
+            text.push(char::from(byte));
+        } else {
+            text.push_str(&format!("\\{:03}", byte));
+        }
+    }
+}
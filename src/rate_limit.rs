@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub queries_per_second: f64,
+    /// Number of queries that may be sent back to back after an idle period.
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+}
+
+fn default_burst() -> u32 {
+    1
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token bucket pacing the queries sent to one name server, together with a total query budget.
+///
+/// Both are shared by every `collect` call made through the same limiter, so the budget applies
+/// to a whole run.
+pub struct RateLimiter {
+    rate_limit: Option<RateLimit>,
+    budget: Option<usize>,
+    used: AtomicUsize,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(rate_limit: Option<RateLimit>, budget: Option<usize>) -> Self {
+        Self {
+            rate_limit,
+            budget,
+            used: AtomicUsize::new(0),
+            bucket: Mutex::new(Bucket {
+                tokens: rate_limit.map_or(0.0, |r| f64::from(r.burst.max(1))),
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(None, None)
+    }
+
+    /// Number of queries let through so far.
+    pub fn used(&self) -> usize {
+        self.used
+            .load(Ordering::SeqCst)
+            .min(self.budget.unwrap_or(usize::MAX))
+    }
+
+    /// Waits until a query may be sent and returns how long it was held back, or `None` if the
+    /// query budget is spent.
+    pub async fn acquire(&self) -> Option<Duration> {
+        if let Some(budget) = self.budget {
+            if self.used.fetch_add(1, Ordering::SeqCst) >= budget {
+                return None;
+            }
+        } else {
+            self.used.fetch_add(1, Ordering::SeqCst);
+        }
+        let rate_limit = match self.rate_limit {
+            Some(rate_limit) if rate_limit.queries_per_second > 0.0 => rate_limit,
+            _ => return Some(Duration::from_secs(0)),
+        };
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.last_refill).as_secs_f64()
+                * rate_limit.queries_per_second;
+            bucket.tokens = (bucket.tokens + refill).min(f64::from(rate_limit.burst.max(1)));
+            bucket.last_refill = now;
+            // take the token even when it is not there yet; the debt is paid by sleeping, which
+            // keeps concurrent callers in line
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                Duration::from_secs(0)
+            } else {
+                Duration::from_secs_f64(-bucket.tokens / rate_limit.queries_per_second)
+            }
+        };
+        if wait > Duration::from_secs(0) {
+            tokio::time::sleep(wait).await;
+        }
+        Some(wait)
+    }
+}
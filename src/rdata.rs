@@ -0,0 +1,597 @@
+//! Presentation-format (RFC 1035 5.1) RDATA parsing into the same typed `RData` a decoded
+//! response carries, so records from the dig backend hash and compare like native ones, and
+//! formatting back for output meant to be read.
+
+use crate::error::*;
+use crate::presentation::{
+    escape, format_character_string, format_name, parse_name, split_unescaped, unescape,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, NaiveDateTime};
+use std::convert::TryInto;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use trust_dns_proto::rr::dnssec::rdata::DNSSECRecordType;
+use trust_dns_proto::rr::rdata::{MX, NULL, SOA, SRV};
+use trust_dns_proto::rr::{Name, RData, RecordType};
+use trust_dns_proto::serialize::binary::{
+    BinDecodable, BinDecoder, BinEncodable, BinEncoder, Restrict,
+};
+
+/// Type code of SVCB (RFC 9460), which this version of trust-dns has no variant for.
+pub const SVCB: u16 = 64;
+/// Type code of HTTPS (RFC 9460).
+pub const HTTPS: u16 = 65;
+
+/// Like `RecordType::from_str`, but also knows SVCB, HTTPS and the generic `TYPE<n>` form.
+pub fn parse_record_type(s: &str) -> Result<RecordType, RDataParseError> {
+    match s.to_ascii_uppercase().as_str() {
+        "SVCB" => Ok(RecordType::Unknown(SVCB)),
+        "HTTPS" => Ok(RecordType::Unknown(HTTPS)),
+        upper => match upper.strip_prefix("TYPE").map(u16::from_str) {
+            Some(Ok(code)) => Ok(RecordType::from(code)),
+            _ => {
+                RecordType::from_str(upper).map_err(|_| RDataParseError::InvalidType(s.to_owned()))
+            }
+        },
+    }
+}
+
+/// The mnemonic of `record_type`, or `TYPE<n>` where trust-dns would only say "Unknown".
+pub fn record_type_name(record_type: RecordType) -> String {
+    match record_type {
+        RecordType::Unknown(SVCB) => "SVCB".to_owned(),
+        RecordType::Unknown(HTTPS) => "HTTPS".to_owned(),
+        RecordType::Unknown(code) | RecordType::DNSSEC(DNSSECRecordType::Unknown(code)) => {
+            format!("TYPE{}", code)
+        }
+        _ => record_type.to_string(),
+    }
+}
+
+/// Parses the RDATA fields of a record, given as tokens with their quotes removed but their
+/// backslash escapes still in place, as `presentation::tokenize` leaves them.
+///
+/// Types without a parser here keep their presentation text in `RData::Unknown`, as before.
+pub fn parse_record_data(
+    tokens: &[&str],
+    record_type: RecordType,
+) -> Result<RData, RDataParseError> {
+    if tokens.first() == Some(&"\\#") {
+        return parse_generic(&tokens[1..], record_type);
+    }
+    let mut fields = Fields::new(tokens, record_type);
+    let rdata = match record_type {
+        RecordType::A | RecordType::AAAA => {
+            let ip_addr =
+                IpAddr::from_str(fields.next()?).map_err(RDataParseError::InvalidIpAddr)?;
+            match ip_addr {
+                IpAddr::V4(ip) => RData::A(ip),
+                IpAddr::V6(ip) => RData::AAAA(ip),
+            }
+        }
+        RecordType::ANAME => RData::ANAME(fields.name()?),
+        RecordType::CNAME => RData::CNAME(fields.name()?),
+        RecordType::NS => RData::NS(fields.name()?),
+        RecordType::PTR => RData::PTR(fields.name()?),
+        RecordType::MX => RData::MX(MX::new(fields.number()?, fields.name()?)),
+        RecordType::TXT => {
+            let mut wire = Vec::new();
+            for token in fields.rest() {
+                let string = unescape(token).map_err(|_| fields.invalid(token))?;
+                if string.len() > 255 {
+                    return Err(fields.invalid(token));
+                }
+                wire.push(string.len() as u8);
+                wire.extend(string);
+            }
+            from_wire(&wire, record_type)?
+        }
+        RecordType::SOA => RData::SOA(SOA::new(
+            fields.name()?,
+            fields.name()?,
+            fields.number()?,
+            fields.number()?,
+            fields.number()?,
+            fields.number()?,
+            fields.number()?,
+        )),
+        RecordType::SRV => RData::SRV(SRV::new(
+            fields.number()?,
+            fields.number()?,
+            fields.number()?,
+            fields.name()?,
+        )),
+        RecordType::CAA => {
+            let mut wire = vec![fields.number::<u8>()?];
+            let tag = fields.next()?;
+            if tag.len() > 255 {
+                return Err(fields.invalid(tag));
+            }
+            wire.push(tag.len() as u8);
+            wire.extend_from_slice(tag.as_bytes());
+            let value = fields.rest().join(" ");
+            wire.extend(unescape(&value).map_err(|_| fields.invalid(&value))?);
+            from_wire(&wire, record_type)?
+        }
+        RecordType::DNSSEC(DNSSECRecordType::DS) => {
+            let mut wire = fields.number::<u16>()?.to_be_bytes().to_vec();
+            wire.push(fields.number()?);
+            wire.push(fields.number()?);
+            wire.extend(fields.hex()?);
+            from_wire(&wire, record_type)?
+        }
+        RecordType::DNSSEC(DNSSECRecordType::DNSKEY) => {
+            let mut wire = fields.number::<u16>()?.to_be_bytes().to_vec();
+            wire.push(fields.number()?);
+            wire.push(fields.number()?);
+            wire.extend(fields.base64()?);
+            from_wire(&wire, record_type)?
+        }
+        RecordType::DNSSEC(DNSSECRecordType::RRSIG) => {
+            let type_covered = parse_record_type(fields.next()?)?;
+            let mut wire = u16::from(type_covered).to_be_bytes().to_vec();
+            wire.push(fields.number()?);
+            wire.push(fields.number()?);
+            wire.extend_from_slice(&fields.number::<u32>()?.to_be_bytes());
+            wire.extend_from_slice(&fields.time()?.to_be_bytes());
+            wire.extend_from_slice(&fields.time()?.to_be_bytes());
+            wire.extend_from_slice(&fields.number::<u16>()?.to_be_bytes());
+            emit_name(&mut wire, &fields.name()?);
+            wire.extend(fields.base64()?);
+            from_wire(&wire, record_type)?
+        }
+        RecordType::Unknown(code @ SVCB) | RecordType::Unknown(code @ HTTPS) => RData::Unknown {
+            code,
+            rdata: NULL::with(parse_svcb(&mut fields)?),
+        },
+        RecordType::NULL => RData::NULL(NULL::with(tokens.join(" ").into_bytes())),
+        RecordType::Unknown(code) => RData::Unknown {
+            code,
+            rdata: NULL::with(tokens.join(" ").into_bytes()),
+        },
+        _ => RData::Unknown {
+            code: record_type.into(),
+            rdata: NULL::with(tokens.join(" ").into_bytes()),
+        },
+    };
+    Ok(rdata)
+}
+
+/// RFC 3597 generic form: `\# <length> <hex>...`.
+fn parse_generic(tokens: &[&str], record_type: RecordType) -> Result<RData, RDataParseError> {
+    let mut fields = Fields::new(tokens, record_type);
+    let length = fields.number::<usize>()?;
+    let wire = if length == 0 {
+        Vec::new()
+    } else {
+        fields.hex()?
+    };
+    if wire.len() != length {
+        return Err(RDataParseError::InvalidField(
+            record_type,
+            format!("{} bytes of RDATA, expected {}", wire.len(), length),
+        ));
+    }
+    match record_type {
+        RecordType::Unknown(code) => Ok(RData::Unknown {
+            code,
+            rdata: NULL::with(wire),
+        }),
+        _ => from_wire(&wire, record_type),
+    }
+}
+
+fn from_wire(wire: &[u8], record_type: RecordType) -> Result<RData, RDataParseError> {
+    let mut decoder = BinDecoder::new(wire);
+    RData::read(&mut decoder, record_type, Restrict::new(wire.len() as u16))
+        .map_err(|e| RDataParseError::InvalidWireData(record_type, e))
+}
+
+fn emit_name(wire: &mut Vec<u8>, name: &Name) {
+    let mut buf = Vec::new();
+    let mut encoder = BinEncoder::new(&mut buf);
+    // the encoder starts out empty, so there is nothing to compress against
+    name.emit(&mut encoder).unwrap();
+    wire.extend(buf);
+}
+
+/// SVCB/HTTPS RDATA (RFC 9460 2.2): priority, target and the SvcParams sorted by key.
+fn parse_svcb(fields: &mut Fields) -> Result<Vec<u8>, RDataParseError> {
+    let mut wire = fields.number::<u16>()?.to_be_bytes().to_vec();
+    emit_name(&mut wire, &fields.name()?);
+    let mut params = Vec::new();
+    for param in fields.rest() {
+        let (key, value) = match param.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (*param, None),
+        };
+        let key = svc_param_key(key).ok_or_else(|| fields.invalid(param))?;
+        let value = match (key, value) {
+            (0, Some(value)) => {
+                let mut keys = Vec::new();
+                for v in value.split(',') {
+                    keys.push(svc_param_key(v).ok_or_else(|| fields.invalid(param))?);
+                }
+                keys.sort_unstable();
+                keys.iter().flat_map(|v| v.to_be_bytes().to_vec()).collect()
+            }
+            (1, Some(value)) => {
+                let mut wire = Vec::new();
+                // the value is unescaped once as a whole and once per item (RFC 9460 A.1)
+                let value = unescape(value)
+                    .ok()
+                    .and_then(|v| String::from_utf8(v).ok())
+                    .ok_or_else(|| fields.invalid(param))?;
+                for alpn in split_unescaped(&value, ',') {
+                    let alpn = unescape(alpn).map_err(|_| fields.invalid(param))?;
+                    if alpn.len() > 255 {
+                        return Err(fields.invalid(param));
+                    }
+                    wire.push(alpn.len() as u8);
+                    wire.extend(alpn);
+                }
+                wire
+            }
+            (2, None) => Vec::new(),
+            (3, Some(value)) => value
+                .parse::<u16>()
+                .map_err(|_| fields.invalid(param))?
+                .to_be_bytes()
+                .to_vec(),
+            (4, Some(value)) => {
+                let mut wire = Vec::new();
+                for v in value.split(',') {
+                    let ip = Ipv4Addr::from_str(v).map_err(RDataParseError::InvalidIpAddr)?;
+                    wire.extend_from_slice(&ip.octets());
+                }
+                wire
+            }
+            (5, Some(value)) => STANDARD.decode(value).map_err(|_| fields.invalid(param))?,
+            (6, Some(value)) => {
+                let mut wire = Vec::new();
+                for v in value.split(',') {
+                    let ip = Ipv6Addr::from_str(v).map_err(RDataParseError::InvalidIpAddr)?;
+                    wire.extend_from_slice(&ip.octets());
+                }
+                wire
+            }
+            (_, Some(value)) if key > 6 => unescape(value).map_err(|_| fields.invalid(param))?,
+            (_, None) if key > 6 => Vec::new(),
+            _ => return Err(fields.invalid(param)),
+        };
+        if value.len() > usize::from(u16::MAX) {
+            return Err(fields.invalid(param));
+        }
+        params.push((key, value));
+    }
+    params.sort_by_key(|(key, _)| *key);
+    for (key, value) in params {
+        wire.extend_from_slice(&key.to_be_bytes());
+        wire.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        wire.extend(value);
+    }
+    Ok(wire)
+}
+
+fn svc_param_key(key: &str) -> Option<u16> {
+    match key {
+        "mandatory" => Some(0),
+        "alpn" => Some(1),
+        "no-default-alpn" => Some(2),
+        "port" => Some(3),
+        "ipv4hint" => Some(4),
+        "ech" => Some(5),
+        "ipv6hint" => Some(6),
+        _ => key.strip_prefix("key")?.parse().ok(),
+    }
+}
+
+/// The RDATA tokens of one record, consumed field by field.
+struct Fields<'a> {
+    tokens: &'a [&'a str],
+    record_type: RecordType,
+}
+
+impl<'a> Fields<'a> {
+    fn new(tokens: &'a [&'a str], record_type: RecordType) -> Self {
+        Self {
+            tokens,
+            record_type,
+        }
+    }
+
+    fn invalid(&self, field: &str) -> RDataParseError {
+        RDataParseError::InvalidField(self.record_type, field.to_owned())
+    }
+
+    fn next(&mut self) -> Result<&'a str, RDataParseError> {
+        let (first, rest) = self
+            .tokens
+            .split_first()
+            .ok_or(RDataParseError::MissingField(self.record_type))?;
+        self.tokens = rest;
+        Ok(first)
+    }
+
+    fn rest(&mut self) -> &'a [&'a str] {
+        std::mem::take(&mut self.tokens)
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, RDataParseError> {
+        let field = self.next()?;
+        field.parse().map_err(|_| self.invalid(field))
+    }
+
+    fn name(&mut self) -> Result<Name, RDataParseError> {
+        let record_type = self.record_type;
+        parse_name(self.next()?).map_err(|e| RDataParseError::InvalidName(record_type, e))
+    }
+
+    /// The remaining tokens as one hex string; dig splits long ones.
+    fn hex(&mut self) -> Result<Vec<u8>, RDataParseError> {
+        let hex = self.rest().concat();
+        if hex.is_empty() {
+            return Err(RDataParseError::MissingField(self.record_type));
+        }
+        if hex.len() % 2 != 0 {
+            return Err(self.invalid(&hex));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| self.invalid(&hex)))
+            .collect()
+    }
+
+    /// The remaining tokens as one base64 string.
+    fn base64(&mut self) -> Result<Vec<u8>, RDataParseError> {
+        let base64 = self.rest().concat();
+        STANDARD.decode(&base64).map_err(|_| self.invalid(&base64))
+    }
+
+    /// An RRSIG timestamp, either `YYYYMMDDHHmmSS` or seconds since the epoch (RFC 4034 3.2).
+    fn time(&mut self) -> Result<u32, RDataParseError> {
+        let field = self.next()?;
+        if field.len() == 14 {
+            let time = NaiveDateTime::parse_from_str(field, "%Y%m%d%H%M%S")
+                .map_err(|_| self.invalid(field))?;
+            // serial number arithmetic, so times past 2106 wrap around
+            Ok(time.and_utc().timestamp() as u32)
+        } else {
+            field.parse().map_err(|_| self.invalid(field))
+        }
+    }
+}
+
+/// Writes RDATA in presentation form, which `parse_record_data` reads back into the same `RData`.
+/// Types without a formatter here, and RDATA that does not decode, are written in the RFC 3597
+/// generic form.
+pub fn format_record_data(rdata: &RData, record_type: RecordType) -> String {
+    let mut buf = Vec::new();
+    let mut encoder = BinEncoder::new(&mut buf);
+    // names in RDATA would otherwise be compressed against each other
+    encoder.set_canonical_names(true);
+    if rdata.emit(&mut encoder).is_err() {
+        return "\\# 0".to_owned();
+    }
+    format_wire(&buf, record_type).unwrap_or_else(|| format_generic(&buf))
+}
+
+fn format_generic(wire: &[u8]) -> String {
+    if wire.is_empty() {
+        return "\\# 0".to_owned();
+    }
+    format!("\\# {} {}", wire.len(), hex(wire))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|v| format!("{:02X}", v)).collect()
+}
+
+fn format_time(time: u32) -> String {
+    match DateTime::from_timestamp(time.into(), 0) {
+        Some(time) => time.format("%Y%m%d%H%M%S").to_string(),
+        None => time.to_string(),
+    }
+}
+
+fn format_wire(wire: &[u8], record_type: RecordType) -> Option<String> {
+    let mut rdata = Wire(wire);
+    let text = match record_type {
+        RecordType::A => Ipv4Addr::from(rdata.u32()?).to_string(),
+        RecordType::AAAA => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(rdata.take(16)?);
+            Ipv6Addr::from(octets).to_string()
+        }
+        RecordType::ANAME | RecordType::CNAME | RecordType::NS | RecordType::PTR => {
+            format_name(&rdata.name()?)
+        }
+        RecordType::MX => format!("{} {}", rdata.u16()?, format_name(&rdata.name()?)),
+        RecordType::TXT => {
+            let mut strings = Vec::new();
+            while !rdata.0.is_empty() {
+                let len = rdata.u8()?;
+                strings.push(format_character_string(rdata.take(len.into())?));
+            }
+            strings.join(" ")
+        }
+        RecordType::SOA => format!(
+            "{} {} {} {} {} {} {}",
+            format_name(&rdata.name()?),
+            format_name(&rdata.name()?),
+            rdata.u32()?,
+            rdata.u32()?,
+            rdata.u32()?,
+            rdata.u32()?,
+            rdata.u32()?
+        ),
+        RecordType::SRV => format!(
+            "{} {} {} {}",
+            rdata.u16()?,
+            rdata.u16()?,
+            rdata.u16()?,
+            format_name(&rdata.name()?)
+        ),
+        RecordType::CAA => {
+            let flags = rdata.u8()?;
+            let tag_len = rdata.u8()?;
+            let tag = std::str::from_utf8(rdata.take(tag_len.into())?).ok()?;
+            format!(
+                "{} {} {}",
+                flags,
+                tag,
+                format_character_string(rdata.rest())
+            )
+        }
+        RecordType::DNSSEC(DNSSECRecordType::DS) => format!(
+            "{} {} {} {}",
+            rdata.u16()?,
+            rdata.u8()?,
+            rdata.u8()?,
+            hex(rdata.rest())
+        ),
+        RecordType::DNSSEC(DNSSECRecordType::DNSKEY) => format!(
+            "{} {} {} {}",
+            rdata.u16()?,
+            rdata.u8()?,
+            rdata.u8()?,
+            STANDARD.encode(rdata.rest())
+        ),
+        RecordType::DNSSEC(DNSSECRecordType::RRSIG) => format!(
+            "{} {} {} {} {} {} {} {} {}",
+            record_type_name(RecordType::from(rdata.u16()?)),
+            rdata.u8()?,
+            rdata.u8()?,
+            rdata.u32()?,
+            format_time(rdata.u32()?),
+            format_time(rdata.u32()?),
+            rdata.u16()?,
+            format_name(&rdata.name()?),
+            STANDARD.encode(rdata.rest())
+        ),
+        RecordType::Unknown(SVCB) | RecordType::Unknown(HTTPS) => format_svcb(&mut rdata)?,
+        _ => return None,
+    };
+    if rdata.0.is_empty() {
+        Some(text)
+    } else {
+        None
+    }
+}
+
+/// The inverse of `parse_svcb`.
+fn format_svcb(rdata: &mut Wire) -> Option<String> {
+    let mut fields = vec![rdata.u16()?.to_string(), format_name(&rdata.name()?)];
+    while !rdata.0.is_empty() {
+        let key = rdata.u16()?;
+        let len = rdata.u16()?;
+        let mut value = Wire(rdata.take(len.into())?);
+        let param = match key {
+            0 => {
+                let mut keys = Vec::new();
+                while !value.0.is_empty() {
+                    keys.push(svc_param_key_name(value.u16()?));
+                }
+                format!("mandatory={}", keys.join(","))
+            }
+            1 => {
+                let mut ids = Vec::new();
+                while !value.0.is_empty() {
+                    let len = value.u8()?;
+                    let id = value.take(len.into())?;
+                    // commas and backslashes in an item are escaped before the whole value is
+                    let mut item = Vec::new();
+                    for &byte in id {
+                        if byte == b',' || byte == b'\\' {
+                            item.push(b'\\');
+                        }
+                        item.push(byte);
+                    }
+                    ids.push(item);
+                }
+                format!("alpn={}", escape(&ids.join(&b',')))
+            }
+            2 if len == 0 => "no-default-alpn".to_owned(),
+            3 => format!("port={}", value.u16()?),
+            4 => {
+                let mut hints = Vec::new();
+                while !value.0.is_empty() {
+                    hints.push(Ipv4Addr::from(value.u32()?).to_string());
+                }
+                format!("ipv4hint={}", hints.join(","))
+            }
+            5 => format!("ech={}", STANDARD.encode(value.rest())),
+            6 => {
+                let mut hints = Vec::new();
+                while !value.0.is_empty() {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(value.take(16)?);
+                    hints.push(Ipv6Addr::from(octets).to_string());
+                }
+                format!("ipv6hint={}", hints.join(","))
+            }
+            _ if key > 6 && len == 0 => svc_param_key_name(key),
+            _ if key > 6 => format!("key{}={}", key, escape(value.rest())),
+            _ => return None,
+        };
+        if !value.0.is_empty() {
+            return None;
+        }
+        fields.push(param);
+    }
+    Some(fields.join(" "))
+}
+
+fn svc_param_key_name(key: u16) -> String {
+    match key {
+        0 => "mandatory".to_owned(),
+        1 => "alpn".to_owned(),
+        2 => "no-default-alpn".to_owned(),
+        3 => "port".to_owned(),
+        4 => "ipv4hint".to_owned(),
+        5 => "ech".to_owned(),
+        6 => "ipv6hint".to_owned(),
+        _ => format!("key{}", key),
+    }
+}
+
+/// RDATA in wire format, consumed field by field.
+struct Wire<'a>(&'a [u8]);
+
+impl<'a> Wire<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(field)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.0)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    /// An uncompressed name, as names in RDATA are once they left the message.
+    fn name(&mut self) -> Option<Name> {
+        let mut decoder = BinDecoder::new(self.0);
+        let name = Name::read(&mut decoder).ok()?;
+        let len = decoder.index();
+        self.take(len)?;
+        Some(name)
+    }
+}
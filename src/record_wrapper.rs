@@ -14,6 +14,10 @@ impl RecordWrapper {
     pub fn unwrap(self) -> Record {
         self.0
     }
+
+    pub fn record(&self) -> &Record {
+        &self.0
+    }
 }
 
 impl std::hash::Hash for RecordWrapper {
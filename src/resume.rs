@@ -0,0 +1,128 @@
+//! Picking up an interrupted crawl where it stopped.
+//!
+//! A crawl saves its parameters as `crawl.json` in the target directory. The chunk files carry
+//! their own headers, so a probe of a chunk is done exactly when the file it writes last is
+//! intact and describes that probe. The SQLite store answers the same question from its `runs`.
+
+use crate::container::{self, crc32, Header};
+use crate::error::*;
+use crate::name_server::NameServer;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+pub const MANIFEST_FILE: &str = "crawl.json";
+
+/// The parameters of a crawl, which must not change when it is resumed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub record_types: Vec<String>,
+    pub name_servers: Vec<String>,
+    /// CRC-32 of the name server entries, see `name_server_checksum`.
+    pub name_server_checksum: u32,
+    pub client_subnets: Vec<String>,
+    pub repeat: usize,
+    pub save_every: usize,
+    pub observation_log: bool,
+    /// Whether the chunks go into the SQLite store rather than files.
+    #[serde(default)]
+    pub sqlite: bool,
+    pub domain_count: usize,
+    /// CRC-32 of the domain names, one per line.
+    pub domain_checksum: u32,
+}
+
+impl Manifest {
+    /// The first parameter that differs from `other`, if any.
+    pub fn mismatch(&self, other: &Manifest) -> Option<&'static str> {
+        if self.record_types != other.record_types {
+            Some("record types")
+        } else if self.name_servers != other.name_servers
+            || self.name_server_checksum != other.name_server_checksum
+        {
+            Some("name servers")
+        } else if self.client_subnets != other.client_subnets {
+            Some("ECS prefixes")
+        } else if self.repeat != other.repeat {
+            Some("repeat")
+        } else if self.save_every != other.save_every {
+            Some("chunk size")
+        } else if self.observation_log != other.observation_log {
+            Some("observation log")
+        } else if self.sqlite != other.sqlite {
+            Some("store")
+        } else if self.domain_count != other.domain_count
+            || self.domain_checksum != other.domain_checksum
+        {
+            Some("domain list")
+        } else {
+            None
+        }
+    }
+}
+
+pub fn domain_checksum(domain_names: &[String]) -> u32 {
+    let mut bytes = Vec::new();
+    for domain_name in domain_names {
+        bytes.extend_from_slice(domain_name.as_bytes());
+        bytes.push(b'\n');
+    }
+    crc32(&bytes)
+}
+
+/// CRC-32 of the name server entries as JSON, leaving out how fast and how much they may be
+/// queried: a budget only lasts one run, so it may well be raised to finish a crawl.
+pub fn name_server_checksum(name_servers: &[NameServer]) -> u32 {
+    let entries = name_servers
+        .iter()
+        .map(|name_server| {
+            let mut entry = serde_json::to_value(name_server).unwrap();
+            let fields = entry.as_object_mut().unwrap();
+            for pacing in ["max_in_flight", "rate_limit", "query_budget"].iter() {
+                fields.remove(*pacing);
+            }
+            entry
+        })
+        .collect::<Vec<_>>();
+    crc32(&serde_json::to_vec(&entries).unwrap())
+}
+
+pub fn save_manifest(dir: &Path, manifest: &Manifest) -> Result<(), ResumeError> {
+    let file = File::create(dir.join(MANIFEST_FILE)).map_err(ResumeError::Io)?;
+    serde_json::to_writer_pretty(file, manifest).map_err(ResumeError::InvalidManifest)
+}
+
+/// Checks that the crawl in `dir` was started with the parameters of `manifest`.
+pub fn check_manifest(dir: &Path, manifest: &Manifest) -> Result<(), ResumeError> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.is_file() {
+        return Err(ResumeError::MissingManifest);
+    }
+    let file = BufReader::new(File::open(path).map_err(ResumeError::Io)?);
+    let saved: Manifest = serde_json::from_reader(file).map_err(ResumeError::InvalidManifest)?;
+    match saved.mismatch(manifest) {
+        Some(parameter) => Err(ResumeError::Mismatch(parameter)),
+        None => Ok(()),
+    }
+}
+
+/// The header of the file at `path` if it is intact and was written for the probe `expected`
+/// describes. Timestamps are not compared.
+pub fn completed(path: &Path, expected: &Header) -> Option<Header> {
+    let file = BufReader::new(File::open(path).ok()?);
+    let header = container::read_header(file).ok()??;
+    let same = header.content == expected.content
+        && header.record_type == expected.record_type
+        && header.name_server == expected.name_server
+        && header.client_subnet == expected.client_subnet
+        && header.repeat == expected.repeat
+        && header.first_rank == expected.first_rank
+        && header.last_rank == expected.last_rank
+        && header.differential == expected.differential;
+    if same {
+        Some(header)
+    } else {
+        None
+    }
+}
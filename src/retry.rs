@@ -0,0 +1,43 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How long to wait for each response and how to retry unanswered queries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub timeout_ms: u64,
+    /// Number of times a query is re-sent after the first attempt failed.
+    pub retries: u32,
+    /// Backoff before the first retry; it doubles with every further retry.
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 5000,
+            retries: 2,
+            backoff_ms: 250,
+            max_backoff_ms: 5000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    /// Delay before the `retry`-th retry (starting at 1), drawn uniformly from the upper half of
+    /// the exponential backoff so that queries failing together do not retry together.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .backoff_ms
+            .saturating_mul(1u64 << retry.saturating_sub(1).min(32))
+            .min(self.max_backoff_ms);
+        let jittered = backoff / 2 + rand::thread_rng().gen_range(0..=backoff - backoff / 2);
+        Duration::from_millis(jittered)
+    }
+}
@@ -0,0 +1,649 @@
+//! SQLite store for collection results, as an alternative to one container file per chunk that
+//! can be queried with plain SQL.
+//!
+//! Every saved chunk is a row of `runs`, holding what a file header would. The rows of the other
+//! tables belong to a run:
+//!
+//! - `records`: one per record in the answers, with its RDATA in presentation form and in wire
+//!   format, how often it was seen and its original TTL if known; `ttls` and `ttl_observations`
+//!   hold the distinct TTLs and every sighting of it.
+//! - `query_outcomes`, `latencies` and `cname_chains`: how each queried name was answered.
+//! - `baseline_ttls`: the original TTL of each RRset a baseline name server answered with.
+//!
+//! A chunk collected again, as when a crawl is resumed, replaces the runs saved of it before.
+//! Names are in presentation form, times in seconds since the Unix epoch.
+
+use crate::collect::{AllDomains, AllQueries, Baseline, DomainStat, QueryOutcome, TtlObservation};
+use crate::container::{Content, Header};
+use crate::error::*;
+use crate::presentation::{format_name, parse_name};
+use crate::rdata::{format_record_data, parse_record_type, record_type_name};
+use crate::record_wrapper::RecordWrapper;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use trust_dns_proto::rr::{Name, Record};
+use trust_dns_proto::serialize::binary::{BinDecodable, BinEncodable};
+
+/// Name of the store in a crawl's target directory.
+pub const STORE_FILE: &str = "results.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS name_servers (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    content TEXT NOT NULL,
+    crate_version TEXT NOT NULL,
+    record_type TEXT NOT NULL,
+    name_server_id INTEGER NOT NULL REFERENCES name_servers (id),
+    client_subnet TEXT,
+    repeat INTEGER NOT NULL,
+    first_rank INTEGER NOT NULL,
+    last_rank INTEGER NOT NULL,
+    differential INTEGER NOT NULL,
+    started_at REAL NOT NULL,
+    finished_at REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS domains (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS records (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    domain_id INTEGER NOT NULL REFERENCES domains (id),
+    record_type TEXT NOT NULL,
+    class TEXT NOT NULL,
+    rdata TEXT NOT NULL,
+    wire BLOB NOT NULL,
+    counts INTEGER NOT NULL,
+    original_ttl INTEGER
+);
+CREATE TABLE IF NOT EXISTS ttls (
+    record_id INTEGER NOT NULL REFERENCES records (id),
+    ttl INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS ttl_observations (
+    record_id INTEGER NOT NULL REFERENCES records (id),
+    observed_at REAL NOT NULL,
+    ttl INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS query_outcomes (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    domain_id INTEGER NOT NULL REFERENCES domains (id),
+    control INTEGER NOT NULL,
+    response_code INTEGER NOT NULL,
+    authoritative INTEGER NOT NULL,
+    recursion_available INTEGER NOT NULL,
+    truncated INTEGER NOT NULL,
+    recursion_desired INTEGER NOT NULL,
+    answer_count INTEGER NOT NULL,
+    authority_count INTEGER NOT NULL,
+    client_subnet_scope INTEGER,
+    count INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS latencies (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    domain_id INTEGER NOT NULL REFERENCES domains (id),
+    latency REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS cname_chains (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    domain_id INTEGER NOT NULL REFERENCES domains (id),
+    chain TEXT NOT NULL,
+    count INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS baseline_ttls (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    domain_id INTEGER NOT NULL REFERENCES domains (id),
+    record_type TEXT NOT NULL,
+    ttl INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS records_run ON records (run_id);
+CREATE INDEX IF NOT EXISTS records_domain ON records (domain_id);
+CREATE INDEX IF NOT EXISTS query_outcomes_run ON query_outcomes (run_id);
+";
+
+pub struct Store {
+    connection: Connection,
+}
+
+impl Store {
+    /// Opens the store at `path`, creating it and its tables if needed.
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        let connection = Connection::open(path).map_err(StoreError::Sqlite)?;
+        // an analyst's query may hold a lock while a crawl writes
+        connection
+            .busy_timeout(Duration::from_secs(60))
+            .map_err(StoreError::Sqlite)?;
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(StoreError::Sqlite)?;
+        Ok(Self { connection })
+    }
+
+    pub fn save_domains(
+        &mut self,
+        header: &Header,
+        all_domains: &AllDomains,
+    ) -> Result<(), StoreError> {
+        let transaction = self.connection.transaction().map_err(StoreError::Sqlite)?;
+        let run_id = insert_run(&transaction, header, Content::AllDomains)?;
+        for (name, records) in all_domains {
+            let domain_id = domain_id(&transaction, name)?;
+            for (record, stat) in records {
+                let record = record.record();
+                transaction
+                    .execute(
+                        "INSERT INTO records (run_id, domain_id, record_type, class, rdata, wire, counts, original_ttl) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                        params![
+                            run_id,
+                            domain_id,
+                            record_type_name(record.record_type()),
+                            record.dns_class().to_string(),
+                            format_record_data(record.rdata(), record.record_type()),
+                            record.to_bytes().map_err(StoreError::InvalidRecord)?,
+                            stat.counts as i64,
+                            stat.original_ttl,
+                        ],
+                    )
+                    .map_err(StoreError::Sqlite)?;
+                let record_id = transaction.last_insert_rowid();
+                for ttl in stat.ttls.iter() {
+                    transaction
+                        .execute(
+                            "INSERT INTO ttls (record_id, ttl) VALUES (?1, ?2)",
+                            params![record_id, ttl],
+                        )
+                        .map_err(StoreError::Sqlite)?;
+                }
+                for observation in stat.observations.iter() {
+                    transaction
+                        .execute(
+                            "INSERT INTO ttl_observations (record_id, observed_at, ttl) VALUES (?1, ?2, ?3)",
+                            params![record_id, seconds(observation.observed_at), observation.ttl],
+                        )
+                        .map_err(StoreError::Sqlite)?;
+                }
+            }
+        }
+        transaction.commit().map_err(StoreError::Sqlite)
+    }
+
+    pub fn save_queries(
+        &mut self,
+        header: &Header,
+        all_queries: &AllQueries,
+    ) -> Result<(), StoreError> {
+        let transaction = self.connection.transaction().map_err(StoreError::Sqlite)?;
+        let run_id = insert_run(&transaction, header, Content::AllQueries)?;
+        for (name, stat) in all_queries {
+            let domain_id = domain_id(&transaction, name)?;
+            let outcomes = stat
+                .outcomes
+                .iter()
+                .map(|(outcome, count)| (false, outcome, *count))
+                .chain(stat.control.iter().map(|outcome| (true, outcome, 1)));
+            for (control, outcome, count) in outcomes {
+                transaction
+                    .execute(
+                        "INSERT INTO query_outcomes (run_id, domain_id, control, response_code, authoritative, recursion_available, truncated, recursion_desired, answer_count, authority_count, client_subnet_scope, count) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                        params![
+                            run_id,
+                            domain_id,
+                            control,
+                            outcome.response_code,
+                            outcome.authoritative,
+                            outcome.recursion_available,
+                            outcome.truncated,
+                            outcome.recursion_desired,
+                            outcome.answer_count as i64,
+                            outcome.authority_count as i64,
+                            outcome.client_subnet_scope,
+                            count as i64,
+                        ],
+                    )
+                    .map_err(StoreError::Sqlite)?;
+            }
+            for latency in stat.latencies.iter() {
+                transaction
+                    .execute(
+                        "INSERT INTO latencies (run_id, domain_id, latency) VALUES (?1, ?2, ?3)",
+                        params![run_id, domain_id, latency.as_secs_f64()],
+                    )
+                    .map_err(StoreError::Sqlite)?;
+            }
+            for (chain, count) in stat.chains.iter() {
+                let chain = chain.iter().map(format_name).collect::<Vec<_>>().join(" ");
+                transaction
+                    .execute(
+                        "INSERT INTO cname_chains (run_id, domain_id, chain, count) VALUES (?1, ?2, ?3, ?4)",
+                        params![run_id, domain_id, chain, *count as i64],
+                    )
+                    .map_err(StoreError::Sqlite)?;
+            }
+        }
+        transaction.commit().map_err(StoreError::Sqlite)
+    }
+
+    pub fn save_baseline(
+        &mut self,
+        header: &Header,
+        baseline: &Baseline,
+    ) -> Result<(), StoreError> {
+        let transaction = self.connection.transaction().map_err(StoreError::Sqlite)?;
+        let run_id = insert_run(&transaction, header, Content::Baseline)?;
+        for ((name, record_type), ttl) in baseline {
+            let domain_id = domain_id(&transaction, name)?;
+            transaction
+                .execute(
+                    "INSERT INTO baseline_ttls (run_id, domain_id, record_type, ttl) VALUES (?1, ?2, ?3, ?4)",
+                    params![run_id, domain_id, record_type_name((*record_type).into()), ttl],
+                )
+                .map_err(StoreError::Sqlite)?;
+        }
+        transaction.commit().map_err(StoreError::Sqlite)
+    }
+
+    /// The headers of every saved chunk, in the order they were saved.
+    pub fn headers(&self) -> Result<Vec<Header>, StoreError> {
+        Ok(self
+            .runs("1 = 1", params![])?
+            .into_iter()
+            .map(|(_, header)| header)
+            .collect())
+    }
+
+    /// The header of the chunk `expected` describes, if it was saved. Timestamps are not
+    /// compared.
+    pub fn completed(&self, expected: &Header) -> Result<Option<Header>, StoreError> {
+        let runs = self.runs(
+            "content = ?1 AND record_type = ?2 AND name_servers.name = ?3 AND client_subnet IS ?4 AND repeat = ?5 AND first_rank = ?6 AND last_rank = ?7 AND differential = ?8",
+            params![
+                content_name(expected.content),
+                expected.record_type,
+                expected.name_server,
+                expected.client_subnet,
+                expected.repeat as i64,
+                expected.first_rank as i64,
+                expected.last_rank as i64,
+                expected.differential,
+            ],
+        )?;
+        Ok(runs.into_iter().last().map(|(_, header)| header))
+    }
+
+    /// Every chunk of `record_type` saved for `name_server`, merged like the chunk files of a
+    /// directory.
+    pub fn read_domains(
+        &self,
+        record_type: &str,
+        name_server: &str,
+    ) -> Result<AllDomains, StoreError> {
+        let mut all_domains = AllDomains::new();
+        for run_id in self.run_ids(Content::AllDomains, record_type, name_server)? {
+            all_domains.extend(self.read_run_domains(run_id)?);
+        }
+        Ok(all_domains)
+    }
+
+    /// Calls `f` with the records of each saved chunk in turn, so that only one is in memory at
+    /// a time.
+    pub fn for_each_domains_chunk(
+        &self,
+        mut f: impl FnMut(&Header, AllDomains),
+    ) -> Result<(), StoreError> {
+        let runs = self.runs("content = ?1", params![content_name(Content::AllDomains)])?;
+        for (run_id, header) in runs {
+            f(&header, self.read_run_domains(run_id)?);
+        }
+        Ok(())
+    }
+
+    fn read_run_domains(&self, run_id: i64) -> Result<AllDomains, StoreError> {
+        let rows = self.select(
+            "SELECT records.id, domains.name, wire, counts, original_ttl FROM records JOIN domains ON domains.id = domain_id WHERE run_id = ?1",
+            run_id,
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, Option<u32>>(4)?,
+                ))
+            },
+        )?;
+        let mut records = HashMap::new();
+        for (id, name, wire, counts, original_ttl) in rows {
+            let record = Record::from_bytes(&wire).map_err(StoreError::InvalidRecord)?;
+            let stat = DomainStat {
+                counts: counts as usize,
+                original_ttl,
+                ..DomainStat::default()
+            };
+            records.insert(id, (read_name(name)?, RecordWrapper::new(record), stat));
+        }
+        let ttls = self.select(
+            "SELECT record_id, ttls.ttl FROM ttls JOIN records ON records.id = record_id WHERE run_id = ?1",
+            run_id,
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, u32>(1)?)),
+        )?;
+        for (id, ttl) in ttls {
+            if let Some((_, _, stat)) = records.get_mut(&id) {
+                stat.ttls.insert(ttl);
+            }
+        }
+        let observations = self.select(
+            "SELECT record_id, observed_at, ttl_observations.ttl FROM ttl_observations JOIN records ON records.id = record_id WHERE run_id = ?1 ORDER BY ttl_observations.rowid",
+            run_id,
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?, row.get::<_, u32>(2)?)),
+        )?;
+        for (id, observed_at, ttl) in observations {
+            if let Some((_, _, stat)) = records.get_mut(&id) {
+                stat.observations.push(TtlObservation {
+                    observed_at: time(observed_at),
+                    ttl,
+                });
+            }
+        }
+        let mut chunk = AllDomains::new();
+        for (name, record, stat) in records.into_values() {
+            chunk.entry(name).or_default().insert(record, stat);
+        }
+        Ok(chunk)
+    }
+
+    pub fn read_queries(
+        &self,
+        record_type: &str,
+        name_server: &str,
+    ) -> Result<AllQueries, StoreError> {
+        let mut all_queries = AllQueries::new();
+        for run_id in self.run_ids(Content::AllQueries, record_type, name_server)? {
+            let mut chunk = AllQueries::new();
+            let outcomes = self.select(
+                "SELECT name, control, response_code, authoritative, recursion_available, truncated, recursion_desired, answer_count, authority_count, client_subnet_scope, count FROM query_outcomes JOIN domains ON domains.id = domain_id WHERE run_id = ?1",
+                run_id,
+                |row| {
+                    let outcome = QueryOutcome {
+                        response_code: row.get(2)?,
+                        authoritative: row.get(3)?,
+                        recursion_available: row.get(4)?,
+                        truncated: row.get(5)?,
+                        recursion_desired: row.get(6)?,
+                        answer_count: row.get::<_, i64>(7)? as usize,
+                        authority_count: row.get::<_, i64>(8)? as usize,
+                        client_subnet_scope: row.get(9)?,
+                    };
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, bool>(1)?,
+                        outcome,
+                        row.get::<_, i64>(10)?,
+                    ))
+                },
+            )?;
+            for (name, control, outcome, count) in outcomes {
+                let stat = chunk.entry(read_name(name)?).or_default();
+                if control {
+                    stat.control = Some(outcome);
+                } else {
+                    stat.outcomes.insert(outcome, count as usize);
+                }
+            }
+            let latencies = self.select(
+                "SELECT name, latency FROM latencies JOIN domains ON domains.id = domain_id WHERE run_id = ?1 ORDER BY latencies.rowid",
+                run_id,
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?)),
+            )?;
+            for (name, latency) in latencies {
+                let stat = chunk.entry(read_name(name)?).or_default();
+                stat.latencies.push(Duration::from_secs_f64(latency));
+            }
+            let chains = self.select(
+                "SELECT name, chain, count FROM cname_chains JOIN domains ON domains.id = domain_id WHERE run_id = ?1",
+                run_id,
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?)),
+            )?;
+            for (name, chain, count) in chains {
+                let chain = chain
+                    .split(' ')
+                    .map(read_name)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let stat = chunk.entry(read_name(name)?).or_default();
+                stat.chains.insert(chain, count as usize);
+            }
+            all_queries.extend(chunk);
+        }
+        Ok(all_queries)
+    }
+
+    pub fn read_baseline(
+        &self,
+        record_type: &str,
+        name_server: &str,
+    ) -> Result<Baseline, StoreError> {
+        let mut baseline = Baseline::new();
+        for run_id in self.run_ids(Content::Baseline, record_type, name_server)? {
+            let rows = self.select(
+                "SELECT name, record_type, ttl FROM baseline_ttls JOIN domains ON domains.id = domain_id WHERE run_id = ?1",
+                run_id,
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, u32>(2)?)),
+            )?;
+            for (name, type_name, ttl) in rows {
+                let record_type = parse_record_type(&type_name)
+                    .map_err(|_| StoreError::InvalidType(type_name))?;
+                baseline.insert((read_name(name)?, record_type.into()), ttl);
+            }
+        }
+        Ok(baseline)
+    }
+
+    /// The rows `sql` selects for the run `run_id`, bound as `?1`.
+    fn select<T>(
+        &self,
+        sql: &str,
+        run_id: i64,
+        map: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>, StoreError> {
+        let mut statement = self.connection.prepare(sql).map_err(StoreError::Sqlite)?;
+        let rows = statement
+            .query_map(params![run_id], map)
+            .map_err(StoreError::Sqlite)?;
+        rows.collect::<Result<_, _>>().map_err(StoreError::Sqlite)
+    }
+
+    fn run_ids(
+        &self,
+        content: Content,
+        record_type: &str,
+        name_server: &str,
+    ) -> Result<Vec<i64>, StoreError> {
+        Ok(self
+            .runs(
+                "content = ?1 AND record_type = ?2 AND name_servers.name = ?3",
+                params![content_name(content), record_type, name_server],
+            )?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect())
+    }
+
+    fn runs(
+        &self,
+        condition: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<(i64, Header)>, StoreError> {
+        let mut statement = self
+            .connection
+            .prepare(&format!(
+                "SELECT runs.id, content, crate_version, record_type, name_servers.name, client_subnet, repeat, first_rank, last_rank, differential, started_at, finished_at FROM runs JOIN name_servers ON name_servers.id = name_server_id WHERE {} ORDER BY runs.id",
+                condition
+            ))
+            .map_err(StoreError::Sqlite)?;
+        let rows = statement
+            .query_map(params, |row| {
+                let header = Header {
+                    crate_version: row.get(2)?,
+                    content: Content::AllDomains,
+                    record_type: row.get(3)?,
+                    name_server: row.get(4)?,
+                    client_subnet: row.get(5)?,
+                    repeat: row.get::<_, i64>(6)? as usize,
+                    first_rank: row.get::<_, i64>(7)? as usize,
+                    last_rank: row.get::<_, i64>(8)? as usize,
+                    differential: row.get(9)?,
+                    started_at: time(row.get(10)?),
+                    finished_at: time(row.get(11)?),
+                };
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, header))
+            })
+            .map_err(StoreError::Sqlite)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(StoreError::Sqlite)?;
+        let mut runs = Vec::new();
+        for (id, content, header) in rows {
+            let content = parse_content(&content).ok_or(StoreError::InvalidContent(content))?;
+            runs.push((id, Header { content, ..header }));
+        }
+        Ok(runs)
+    }
+}
+
+fn insert_run(
+    transaction: &Transaction,
+    header: &Header,
+    content: Content,
+) -> Result<i64, StoreError> {
+    transaction
+        .execute(
+            "INSERT OR IGNORE INTO name_servers (name) VALUES (?1)",
+            params![header.name_server],
+        )
+        .map_err(StoreError::Sqlite)?;
+    let name_server_id: i64 = transaction
+        .query_row(
+            "SELECT id FROM name_servers WHERE name = ?1",
+            params![header.name_server],
+            |row| row.get(0),
+        )
+        .map_err(StoreError::Sqlite)?;
+    delete_runs(transaction, header, content, name_server_id)?;
+    transaction
+        .execute(
+            "INSERT INTO runs (content, crate_version, record_type, name_server_id, client_subnet, repeat, first_rank, last_rank, differential, started_at, finished_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                content_name(content),
+                header.crate_version,
+                header.record_type,
+                name_server_id,
+                header.client_subnet,
+                header.repeat as i64,
+                header.first_rank as i64,
+                header.last_rank as i64,
+                header.differential,
+                seconds(header.started_at),
+                seconds(header.finished_at),
+            ],
+        )
+        .map_err(StoreError::Sqlite)?;
+    Ok(transaction.last_insert_rowid())
+}
+
+fn delete_runs(
+    transaction: &Transaction,
+    header: &Header,
+    content: Content,
+    name_server_id: i64,
+) -> Result<(), StoreError> {
+    let runs = "SELECT id FROM runs WHERE content = ?1 AND record_type = ?2 AND name_server_id = ?3 AND client_subnet IS ?4 AND repeat = ?5 AND first_rank = ?6 AND last_rank = ?7 AND differential = ?8";
+    let records = format!("SELECT id FROM records WHERE run_id IN ({})", runs);
+    let statements = [
+        format!("DELETE FROM ttls WHERE record_id IN ({})", records),
+        format!(
+            "DELETE FROM ttl_observations WHERE record_id IN ({})",
+            records
+        ),
+        format!("DELETE FROM records WHERE run_id IN ({})", runs),
+        format!("DELETE FROM query_outcomes WHERE run_id IN ({})", runs),
+        format!("DELETE FROM latencies WHERE run_id IN ({})", runs),
+        format!("DELETE FROM cname_chains WHERE run_id IN ({})", runs),
+        format!("DELETE FROM baseline_ttls WHERE run_id IN ({})", runs),
+        format!("DELETE FROM runs WHERE id IN ({})", runs),
+    ];
+    for statement in statements.iter() {
+        transaction
+            .execute(
+                statement,
+                params![
+                    content_name(content),
+                    header.record_type,
+                    name_server_id,
+                    header.client_subnet,
+                    header.repeat as i64,
+                    header.first_rank as i64,
+                    header.last_rank as i64,
+                    header.differential,
+                ],
+            )
+            .map_err(StoreError::Sqlite)?;
+    }
+    Ok(())
+}
+
+fn domain_id(transaction: &Transaction, name: &Name) -> Result<i64, StoreError> {
+    let name = format_name(name);
+    let id = transaction
+        .query_row(
+            "SELECT id FROM domains WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(StoreError::Sqlite)?;
+    match id {
+        Some(id) => Ok(id),
+        None => {
+            transaction
+                .execute("INSERT INTO domains (name) VALUES (?1)", params![name])
+                .map_err(StoreError::Sqlite)?;
+            Ok(transaction.last_insert_rowid())
+        }
+    }
+}
+
+fn read_name<S: AsRef<str>>(name: S) -> Result<Name, StoreError> {
+    parse_name(name.as_ref()).map_err(|e| StoreError::InvalidName(name.as_ref().to_owned(), e))
+}
+
+fn content_name(content: Content) -> &'static str {
+    match content {
+        Content::AllDomains => "AllDomains",
+        Content::AllQueries => "AllQueries",
+        Content::Baseline => "Baseline",
+    }
+}
+
+fn parse_content(content: &str) -> Option<Content> {
+    match content {
+        "AllDomains" => Some(Content::AllDomains),
+        "AllQueries" => Some(Content::AllQueries),
+        "Baseline" => Some(Content::Baseline),
+        _ => None,
+    }
+}
+
+fn seconds(time: SystemTime) -> f64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+fn time(seconds: f64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs_f64(seconds)
+}
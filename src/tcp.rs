@@ -0,0 +1,75 @@
+use crate::error::*;
+use crate::transport::{Response, Transport};
+use async_trait::async_trait;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use trust_dns_proto::op::Message;
+
+pub struct TcpTransport {
+    name_server: SocketAddr,
+}
+
+impl TcpTransport {
+    pub fn new(name_server: SocketAddr) -> Self {
+        Self { name_server }
+    }
+}
+
+#[async_trait]
+impl Transport for TcpTransport {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError> {
+        Ok(Response {
+            message: exchange(self.name_server, request).await?,
+            tcp_fallback: false,
+            setup: Duration::default(),
+        })
+    }
+}
+
+/// Sends `request` to `name_server` over TCP, using the 2-byte length framing of RFC 1035 4.2.2.
+///
+/// Like [`crate::udp::exchange`] this may wait forever on a silent server.
+pub async fn exchange(name_server: SocketAddr, request: &Message) -> Result<Message, QueryError> {
+    let mut stream = TcpStream::connect(name_server)
+        .await
+        .map_err(QueryError::IoError)?;
+    exchange_framed(&mut stream, request).await
+}
+
+/// Runs one length-framed exchange over an already established stream.
+pub async fn exchange_framed<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    request: &Message,
+) -> Result<Message, QueryError> {
+    let bytes = request.to_vec().map_err(QueryError::TrustDnsProtoError)?;
+    if bytes.len() > u16::MAX as usize {
+        return Err(QueryError::MessageTooLong(bytes.len()));
+    }
+    let mut framed = Vec::with_capacity(bytes.len() + 2);
+    framed.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    framed.extend_from_slice(&bytes);
+    stream
+        .write_all(&framed)
+        .await
+        .map_err(QueryError::IoError)?;
+    stream.flush().await.map_err(QueryError::IoError)?;
+
+    loop {
+        let mut len = [0u8; 2];
+        stream
+            .read_exact(&mut len)
+            .await
+            .map_err(QueryError::IoError)?;
+        let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream
+            .read_exact(&mut buf)
+            .await
+            .map_err(QueryError::IoError)?;
+        let response = Message::from_vec(&buf).map_err(QueryError::TrustDnsProtoError)?;
+        if response.id() == request.id() {
+            return Ok(response);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::error::*;
+use crate::tcp;
+use crate::transport::{Response, Transport};
+use async_trait::async_trait;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, RootCertStore};
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use trust_dns_proto::op::Message;
+
+pub const DEFAULT_PORT: u16 = 853;
+
+/// How many established connections are kept open between queries (RFC 7858 3.4).
+const MAX_IDLE: usize = 8;
+
+/// Sends each query over a connection of its own, reusing the connections of earlier queries
+/// rather than paying for a TCP and TLS handshake every time.
+pub struct TlsTransport {
+    name_server: SocketAddr,
+    server_name: String,
+    config: Arc<ClientConfig>,
+    idle: Mutex<Vec<TlsStream<TcpStream>>>,
+}
+
+impl TlsTransport {
+    pub fn new(name_server: SocketAddr, server_name: String, config: Arc<ClientConfig>) -> Self {
+        Self {
+            name_server,
+            server_name,
+            config,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Hands `stream` back for the next query, unless enough are idle already.
+    fn release(&self, stream: TlsStream<TcpStream>) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < MAX_IDLE {
+            idle.push(stream);
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for TlsTransport {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError> {
+        let start = Instant::now();
+        let reused = self.idle.lock().unwrap().pop();
+        if let Some(mut stream) = reused {
+            // a response with another ID is skipped rather than taken for this one's
+            match tcp::exchange_framed(&mut stream, request).await {
+                Ok(message) => {
+                    self.release(stream);
+                    return Ok(Response {
+                        message,
+                        tcp_fallback: false,
+                        setup: start.elapsed(),
+                    });
+                }
+                // the server may have closed the connection while it sat idle
+                Err(QueryError::IoError(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut stream = connect(self.name_server, &self.server_name, self.config.clone()).await?;
+        let setup = start.elapsed();
+        let message = tcp::exchange_framed(&mut stream, request).await?;
+        self.release(stream);
+        Ok(Response {
+            message,
+            tcp_fallback: false,
+            setup,
+        })
+    }
+}
+
+/// Builds a client config trusting the webpki roots, plus the PEM certificates in `ca_file` if
+/// given (e.g. for resolvers with a private or self-signed certificate).
+pub fn client_config(ca_file: Option<&Path>) -> Result<Arc<ClientConfig>, QueryError> {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(ca_file) = ca_file {
+        for cert in
+            CertificateDer::pem_file_iter(ca_file).map_err(QueryError::InvalidCertificate)?
+        {
+            roots
+                .add(cert.map_err(QueryError::InvalidCertificate)?)
+                .map_err(QueryError::TlsError)?;
+        }
+    }
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(QueryError::TlsError)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Sends `request` to `name_server` over DNS-over-TLS (RFC 7858) on a connection of its own,
+/// verifying the certificate against `server_name`.
+pub async fn exchange(
+    name_server: SocketAddr,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+    request: &Message,
+) -> Result<Message, QueryError> {
+    let mut stream = connect(name_server, server_name, config).await?;
+    tcp::exchange_framed(&mut stream, request).await
+}
+
+/// Opens a TLS connection to `name_server`, verifying the certificate against `server_name`.
+async fn connect(
+    name_server: SocketAddr,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+) -> Result<TlsStream<TcpStream>, QueryError> {
+    let server_name =
+        ServerName::try_from(server_name.to_owned()).map_err(QueryError::InvalidTlsServerName)?;
+    let socket = TcpStream::connect(name_server)
+        .await
+        .map_err(QueryError::IoError)?;
+    TlsConnector::from(config)
+        .connect(server_name, socket)
+        .await
+        .map_err(QueryError::IoError)
+}
@@ -0,0 +1,52 @@
+use crate::dig::DigTransport;
+use crate::error::*;
+use crate::https::{self, HttpsTransport};
+use crate::name_server::{NameServer, TransportKind};
+use crate::tcp::TcpTransport;
+use crate::tls::{self, TlsTransport};
+use crate::udp::UdpTransport;
+use async_trait::async_trait;
+use std::time::Duration;
+use trust_dns_proto::op::Message;
+
+pub struct Response {
+    pub message: Message,
+    /// Whether the UDP response was truncated and the query was retried over TCP.
+    pub tcp_fallback: bool,
+    /// Time spent setting up a connection before the query went out, which is left out of its
+    /// latency.
+    pub setup: Duration,
+}
+
+/// A backend able to deliver a single DNS query to a name server.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError>;
+}
+
+/// Builds the backend selected by `name_server.transport`.
+pub fn from_name_server(name_server: &NameServer) -> Result<Box<dyn Transport>, QueryError> {
+    let addr = name_server
+        .socket_addr()
+        .map_err(QueryError::InvalidNameServer)?;
+    Ok(match name_server.transport {
+        TransportKind::Udp => Box::new(UdpTransport::new(addr)),
+        TransportKind::Tcp => Box::new(TcpTransport::new(addr)),
+        TransportKind::Dig => Box::new(DigTransport::new(addr)),
+        TransportKind::Tls => Box::new(TlsTransport::new(
+            addr,
+            name_server.tls_server_name().to_owned(),
+            tls::client_config(name_server.tls_ca_file.as_deref())?,
+        )),
+        TransportKind::Https => {
+            let url = name_server.https_url();
+            let domain = reqwest::Url::parse(&url)
+                .map_err(|_| QueryError::InvalidUrl(url.clone()))?
+                .domain()
+                .map(str::to_owned);
+            let tls_config = tls::client_config(name_server.tls_ca_file.as_deref())?;
+            let client = https::client(&tls_config, domain.as_deref().map(|d| (d, addr)))?;
+            Box::new(HttpsTransport::new(client, url, name_server.http_method))
+        }
+    })
+}
@@ -0,0 +1,87 @@
+use crate::error::*;
+use crate::tcp;
+use crate::transport::{Response, Transport};
+use async_trait::async_trait;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use trust_dns_proto::op::Message;
+
+pub const DEFAULT_PORT: u16 = 53;
+const MAX_RESPONSE_SIZE: usize = 4096;
+
+/// Plain DNS over UDP, retrying over TCP when the response is truncated.
+pub struct UdpTransport {
+    name_server: SocketAddr,
+}
+
+impl UdpTransport {
+    pub fn new(name_server: SocketAddr) -> Self {
+        Self { name_server }
+    }
+}
+
+#[async_trait]
+impl Transport for UdpTransport {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError> {
+        let message = exchange(self.name_server, request).await?;
+        if message.truncated() {
+            let message = tcp::exchange(self.name_server, request).await?;
+            Ok(Response {
+                message,
+                tcp_fallback: true,
+                setup: Duration::default(),
+            })
+        } else {
+            Ok(Response {
+                message,
+                tcp_fallback: false,
+                setup: Duration::default(),
+            })
+        }
+    }
+}
+
+/// Sends `request` to `name_server` over UDP and waits for the matching response.
+///
+/// There is no timeout here: the collector bounds every attempt with its retry policy.
+pub async fn exchange(name_server: SocketAddr, request: &Message) -> Result<Message, QueryError> {
+    let bind_addr: SocketAddr = if name_server.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(bind_addr)
+        .await
+        .map_err(QueryError::IoError)?;
+    socket
+        .connect(name_server)
+        .await
+        .map_err(QueryError::IoError)?;
+    let bytes = request.to_vec().map_err(QueryError::TrustDnsProtoError)?;
+    socket.send(&bytes).await.map_err(QueryError::IoError)?;
+
+    let mut buf = [0u8; MAX_RESPONSE_SIZE];
+    loop {
+        let len = socket.recv(&mut buf).await.map_err(QueryError::IoError)?;
+        let response = match Message::from_vec(&buf[..len]) {
+            Ok(response) => response,
+            Err(_) if is_truncated_response(&buf[..len], request.id()) => {
+                // the records may have been cut mid-way; only the header is meaningful
+                let mut response = Message::new();
+                response.set_id(request.id()).set_truncated(true);
+                return Ok(response);
+            }
+            // not a DNS message; keep waiting for the real response
+            Err(_) => continue,
+        };
+        if response.id() == request.id() && response.queries() == request.queries() {
+            return Ok(response);
+        }
+    }
+}
+
+fn is_truncated_response(buf: &[u8], id: u16) -> bool {
+    const TC_MASK: u8 = 0b0000_0010;
+    buf.len() >= 12 && u16::from_be_bytes([buf[0], buf[1]]) == id && buf[2] & TC_MASK != 0
+}
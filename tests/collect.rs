@@ -0,0 +1,474 @@
+mod common;
+
+use common::fqdn;
+use common::mock_server::MockServer;
+use dns_collect::collect::{
+    collect, AllDomains, AllQueries, Baseline, Collector, DomainStat, QueryStat, TtlObservation,
+};
+use dns_collect::ecs::ClientSubnet;
+use dns_collect::name_server::NameServer;
+use serde_json::json;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use trust_dns_proto::op::ResponseCode;
+use trust_dns_proto::rr::{RData, RecordType};
+
+fn name_server(server: &MockServer, transport: &str) -> NameServer {
+    serde_json::from_value(json!({
+        "name": "mock",
+        "host": "127.0.0.1",
+        "port": server.addr().port(),
+        "transport": transport,
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn collects_only_cached_names() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("cached.example"),
+        3600,
+        RData::A(Ipv4Addr::new(192, 0, 2, 10)),
+    );
+    let domain_names = vec!["cached.example".to_owned(), "uncached.example".to_owned()];
+    let mut all_domains = AllDomains::new();
+    let meta = collect(
+        &name_server(&server, "udp"),
+        &domain_names,
+        RecordType::A,
+        3,
+        &mut all_domains,
+        &mut AllQueries::new(),
+    )
+    .await;
+    assert_eq!(meta.repeat_valid, 3);
+    assert_eq!(meta.response_valid, 3);
+    assert_eq!(meta.in_queries, 3);
+    assert_eq!(meta.tcp_fallbacks, 0);
+    assert_eq!(all_domains.len(), 1);
+    let (_, stat) = all_domains[&fqdn("cached.example")].iter().next().unwrap();
+    assert_eq!(stat.counts, 3);
+    assert!(stat.ttls.iter().all(|ttl| *ttl <= 3600 && *ttl > 3500));
+
+    let queries = server.queries();
+    assert_eq!(queries.len(), 6);
+    assert!(queries.iter().all(|q| !q.recursion_desired && !q.over_tcp));
+}
+
+#[tokio::test]
+async fn counts_cname_targets_as_not_in_queries() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("www.example"),
+        300,
+        RData::CNAME(fqdn("cdn.example.net")),
+    );
+    server.cache(
+        &fqdn("cdn.example.net"),
+        60,
+        RData::A(Ipv4Addr::new(192, 0, 2, 20)),
+    );
+    let mut all_domains = AllDomains::new();
+    let meta = collect(
+        &name_server(&server, "udp"),
+        &["www.example".to_owned()],
+        RecordType::A,
+        1,
+        &mut all_domains,
+        &mut AllQueries::new(),
+    )
+    .await;
+    assert_eq!(meta.in_queries, 1);
+    assert_eq!(meta.not_in_queries, 1);
+    assert!(all_domains.contains_key(&fqdn("www.example")));
+    assert!(all_domains.contains_key(&fqdn("cdn.example.net")));
+}
+
+#[tokio::test]
+async fn falls_back_to_tcp_on_truncation() {
+    let server = MockServer::start();
+    for i in 0..40 {
+        server.cache(
+            &fqdn("big.example"),
+            300,
+            RData::A(Ipv4Addr::new(192, 0, 2, i)),
+        );
+    }
+    server.set_max_udp_size(512);
+    let mut all_domains = AllDomains::new();
+    let meta = collect(
+        &name_server(&server, "udp"),
+        &["big.example".to_owned()],
+        RecordType::A,
+        2,
+        &mut all_domains,
+        &mut AllQueries::new(),
+    )
+    .await;
+    assert_eq!(meta.repeat_valid, 2);
+    assert_eq!(meta.tcp_fallbacks, 2);
+    assert_eq!(meta.response_valid, 80);
+    assert_eq!(all_domains[&fqdn("big.example")].len(), 40);
+    let over_tcp = server.queries().iter().filter(|q| q.over_tcp).count();
+    assert_eq!(over_tcp, 2);
+}
+
+#[tokio::test]
+async fn refused_names_yield_no_records() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("refused.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 30)),
+    );
+    server.set_rcode(&fqdn("refused.example"), ResponseCode::Refused);
+    let mut all_domains = AllDomains::new();
+    let meta = collect(
+        &name_server(&server, "tcp"),
+        &["refused.example".to_owned()],
+        RecordType::A,
+        1,
+        &mut all_domains,
+        &mut AllQueries::new(),
+    )
+    .await;
+    assert_eq!(meta.repeat_valid, 1);
+    assert!(all_domains.is_empty());
+}
+
+#[tokio::test]
+async fn records_outcome_per_queried_name() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("www.example"),
+        300,
+        RData::CNAME(fqdn("cdn.example.net")),
+    );
+    server.cache(
+        &fqdn("cdn.example.net"),
+        60,
+        RData::A(Ipv4Addr::new(192, 0, 2, 20)),
+    );
+    server.set_rcode(&fqdn("refused.example"), ResponseCode::Refused);
+    let domain_names = ["www.example", "refused.example", "uncached.example"]
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    let mut all_queries = AllQueries::new();
+    collect(
+        &name_server(&server, "udp"),
+        &domain_names,
+        RecordType::A,
+        2,
+        &mut AllDomains::new(),
+        &mut all_queries,
+    )
+    .await;
+    // CNAME targets are not queried, so only the three probed names show up
+    assert_eq!(all_queries.len(), 3);
+    let outcome = |name: &str| {
+        let stat = &all_queries[&fqdn(name)];
+        assert_eq!(stat.outcomes.len(), 1);
+        let (outcome, count) = stat.outcomes.iter().next().unwrap();
+        assert_eq!(*count, 2);
+        *outcome
+    };
+
+    let answered = outcome("www.example");
+    assert_eq!(answered.response_code(), ResponseCode::NoError);
+    assert_eq!(answered.answer_count, 2);
+    assert!(answered.recursion_available);
+    assert!(!answered.recursion_desired);
+    assert!(!answered.authoritative);
+    assert!(!answered.truncated);
+
+    let refused = outcome("refused.example");
+    assert_eq!(refused.response_code(), ResponseCode::Refused);
+    assert_eq!(refused.answer_count, 0);
+
+    let uncached = outcome("uncached.example");
+    assert_eq!(uncached.response_code(), ResponseCode::NoError);
+    assert_eq!(uncached.answer_count, 0);
+    assert_eq!(uncached.authority_count, 0);
+}
+
+#[tokio::test]
+async fn latency_tells_cache_hits_from_misses() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("cached.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 10)),
+    );
+    server.set_miss_delay(Duration::from_millis(100));
+    let domain_names = vec!["cached.example".to_owned(), "uncached.example".to_owned()];
+    let mut all_queries = AllQueries::new();
+    collect(
+        &name_server(&server, "udp"),
+        &domain_names,
+        RecordType::A,
+        3,
+        &mut AllDomains::new(),
+        &mut all_queries,
+    )
+    .await;
+    let hit = all_queries[&fqdn("cached.example")].latency().unwrap();
+    let miss = all_queries[&fqdn("uncached.example")].latency().unwrap();
+    assert_eq!(all_queries[&fqdn("cached.example")].latencies.len(), 3);
+    assert!(hit.p95 < Duration::from_millis(100), "{:?}", hit);
+    assert!(miss.min >= Duration::from_millis(100), "{:?}", miss);
+}
+
+#[test]
+fn latency_percentiles() {
+    assert_eq!(QueryStat::default().latency(), None);
+    let stat = QueryStat {
+        latencies: (1..=20).rev().map(Duration::from_millis).collect(),
+        ..QueryStat::default()
+    };
+    let latency = stat.latency().unwrap();
+    assert_eq!(latency.min, Duration::from_millis(1));
+    assert_eq!(latency.median, Duration::from_millis(10));
+    assert_eq!(latency.p95, Duration::from_millis(19));
+}
+
+#[tokio::test]
+async fn differential_mode_sends_recursive_controls_last() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("cached.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 10)),
+    );
+    for name in ["cached.example", "resolvable.example"].iter() {
+        server.upstream(&fqdn(name), 300, RData::A(Ipv4Addr::new(192, 0, 2, 10)));
+    }
+    let mut name_server = name_server(&server, "udp");
+    name_server.differential = true;
+    let domain_names = ["cached.example", "resolvable.example", "missing.example"]
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    let mut all_domains = AllDomains::new();
+    let mut all_queries = AllQueries::new();
+    let meta = collect(
+        &name_server,
+        &domain_names,
+        RecordType::A,
+        2,
+        &mut all_domains,
+        &mut all_queries,
+    )
+    .await;
+    assert_eq!(meta.controls, 3);
+    assert_eq!(meta.snooping_misses, 1);
+    assert!(!all_queries[&fqdn("cached.example")].missed_by_snooping());
+    assert!(all_queries[&fqdn("resolvable.example")].missed_by_snooping());
+    assert!(!all_queries[&fqdn("missing.example")].missed_by_snooping());
+    let control = all_queries[&fqdn("resolvable.example")].control.unwrap();
+    assert!(control.recursion_desired);
+    assert_eq!(control.answer_count, 1);
+    // control answers are not cache observations
+    assert_eq!(all_domains.len(), 1);
+    assert_eq!(meta.response_valid, 2);
+
+    let queries = server.queries();
+    assert_eq!(queries.len(), 9);
+    assert!(queries[..6].iter().all(|q| !q.recursion_desired));
+    assert!(queries[6..].iter().all(|q| q.recursion_desired));
+}
+
+#[tokio::test]
+async fn client_subnet_selects_cache_slice() {
+    let server = MockServer::start();
+    let slice = ClientSubnet::from_str("198.51.100.0/24").unwrap();
+    for name in ["everywhere.example", "sliced.example"].iter() {
+        server.cache(&fqdn(name), 300, RData::A(Ipv4Addr::new(192, 0, 2, 10)));
+    }
+    server.cache_in_slice(&fqdn("sliced.example"), slice);
+    let domain_names = vec!["everywhere.example".to_owned(), "sliced.example".to_owned()];
+    let mut collector = Collector::new(&name_server(&server, "udp")).unwrap();
+
+    let mut all_domains = AllDomains::new();
+    let mut all_queries = AllQueries::new();
+    collector
+        .collect(
+            &domain_names,
+            RecordType::A,
+            1,
+            &mut all_domains,
+            &mut all_queries,
+        )
+        .await;
+    assert_eq!(all_domains.len(), 1);
+    let (outcome, _) = all_queries[&fqdn("everywhere.example")]
+        .outcomes
+        .iter()
+        .next()
+        .unwrap();
+    assert_eq!(outcome.client_subnet_scope, None);
+
+    collector.set_client_subnet(Some(slice));
+    let mut all_domains = AllDomains::new();
+    let mut all_queries = AllQueries::new();
+    collector
+        .collect(
+            &domain_names,
+            RecordType::A,
+            1,
+            &mut all_domains,
+            &mut all_queries,
+        )
+        .await;
+    assert_eq!(all_domains.len(), 2);
+    let scope = |name: &str| {
+        let (outcome, _) = all_queries[&fqdn(name)].outcomes.iter().next().unwrap();
+        outcome.client_subnet_scope
+    };
+    assert_eq!(scope("everywhere.example"), Some(0));
+    assert_eq!(scope("sliced.example"), Some(24));
+    let queries = server.queries();
+    assert!(queries[..2].iter().all(|q| q.client_subnet.is_none()));
+    assert!(queries[2..].iter().all(|q| q.client_subnet == Some(slice)));
+}
+
+#[tokio::test]
+async fn reconstructs_cname_chains() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("www.example"),
+        300,
+        RData::CNAME(fqdn("www.example.cdn.net")),
+    );
+    server.cache(
+        &fqdn("www.example.cdn.net"),
+        300,
+        RData::CNAME(fqdn("edge.cdn.net")),
+    );
+    server.cache(
+        &fqdn("edge.cdn.net"),
+        20,
+        RData::A(Ipv4Addr::new(192, 0, 2, 80)),
+    );
+    server.cache(
+        &fqdn("loop-a.example"),
+        300,
+        RData::CNAME(fqdn("loop-b.example")),
+    );
+    server.cache(
+        &fqdn("loop-b.example"),
+        300,
+        RData::CNAME(fqdn("loop-a.example")),
+    );
+    server.cache(
+        &fqdn("plain.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+    );
+    let domain_names = ["www.example", "loop-a.example", "plain.example"]
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    let mut all_domains = AllDomains::new();
+    let mut all_queries = AllQueries::new();
+    collect(
+        &name_server(&server, "udp"),
+        &domain_names,
+        RecordType::A,
+        3,
+        &mut all_domains,
+        &mut all_queries,
+    )
+    .await;
+    let www = &all_queries[&fqdn("www.example")];
+    let chain = vec![
+        fqdn("www.example"),
+        fqdn("www.example.cdn.net"),
+        fqdn("edge.cdn.net"),
+    ];
+    assert_eq!(www.chains.len(), 1);
+    assert_eq!(www.chains[&chain], 3);
+    assert_eq!(www.chain_depth(), 2);
+    // the terminal records stay filed under their owner
+    assert!(all_domains.contains_key(&fqdn("edge.cdn.net")));
+
+    let looped = &all_queries[&fqdn("loop-a.example")];
+    assert_eq!(looped.chain_depth(), 1);
+    assert!(all_queries[&fqdn("plain.example")].chains.is_empty());
+}
+
+#[test]
+fn estimates_cache_fills_from_remaining_ttls() {
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let seen = |after: u64, ttl: u32| TtlObservation {
+        observed_at: start + Duration::from_secs(after),
+        ttl,
+    };
+    let stat = DomainStat {
+        counts: 5,
+        // filled 100s before the first probe, then again when it expired at +200s
+        observations: vec![
+            seen(0, 200),
+            seen(60, 140),
+            seen(121, 80),
+            seen(230, 270),
+            seen(290, 210),
+        ],
+        ..DomainStat::default()
+    };
+    assert_eq!(
+        stat.insertion_times(300)[..2],
+        [
+            start - Duration::from_secs(100),
+            start - Duration::from_secs(100)
+        ]
+    );
+    assert_eq!(
+        stat.cache_fills(300),
+        [
+            start - Duration::from_secs(100),
+            start + Duration::from_secs(200)
+        ]
+    );
+    // a TTL above the original one tells nothing about the insertion
+    assert_eq!(stat.insertion_times(250).len(), 4);
+}
+
+#[tokio::test]
+async fn baseline_keeps_original_ttls() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("www.example"),
+        300,
+        RData::CNAME(fqdn("cdn.example.net")),
+    );
+    server.cache(
+        &fqdn("cdn.example.net"),
+        60,
+        RData::A(Ipv4Addr::new(192, 0, 2, 20)),
+    );
+    let collector = Collector::new(&name_server(&server, "udp")).unwrap();
+    let mut baseline = Baseline::new();
+    let meta = collector
+        .collect_baseline(
+            &["www.example".to_owned(), "nothing.example".to_owned()],
+            RecordType::A,
+            &mut baseline,
+        )
+        .await;
+    assert_eq!(meta.repeat_valid, 1);
+    assert_eq!(baseline.len(), 2);
+    assert_eq!(
+        baseline[&(fqdn("www.example"), u16::from(RecordType::CNAME))],
+        300
+    );
+    assert_eq!(
+        baseline[&(fqdn("cdn.example.net"), u16::from(RecordType::A))],
+        60
+    );
+    let queries = server.queries();
+    assert_eq!(queries.len(), 2);
+    assert!(queries.iter().all(|v| v.recursion_desired));
+}
@@ -0,0 +1,66 @@
+//! Fixtures for running the crawler and reader binaries against mock servers, all in one
+//! directory: the name server list, the top list and the `out` target directory.
+
+use super::mock_server::MockServer;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+pub const NAME_SERVERS_FILE: &str = "name_servers.json";
+pub const TOP_LIST_FILE: &str = "top.csv";
+pub const TARGET_DIR: &str = "out";
+
+/// The name server list entry for `server`, to which tests add their own settings.
+pub fn name_server(name: &str, server: &MockServer) -> Value {
+    json!({
+        "name": name,
+        "host": "127.0.0.1",
+        "port": server.addr().port(),
+    })
+}
+
+pub fn write_name_servers(dir: &Path, name_servers: &[Value]) {
+    fs::write(
+        dir.join(NAME_SERVERS_FILE),
+        serde_json::to_vec(name_servers).unwrap(),
+    )
+    .unwrap();
+}
+
+/// Writes `domains` as a top list, ranked in order from 1.
+pub fn write_top_list(dir: &Path, domains: &[&str]) {
+    let csv = domains
+        .iter()
+        .enumerate()
+        .map(|(i, domain)| format!("{},{}\n", i + 1, domain))
+        .collect::<String>();
+    fs::write(dir.join(TOP_LIST_FILE), csv).unwrap();
+}
+
+/// Crawls the top `k` domains into the target directory, creating it if needed. `extra` goes
+/// after the other arguments, with flags as well as the client subnets to crawl.
+pub fn run_crawler(dir: &Path, record_types: &str, k: usize, extra: &[&str]) -> Output {
+    fs::create_dir_all(dir.join(TARGET_DIR)).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_crawler"))
+        .current_dir(dir)
+        .arg(record_types)
+        .arg(NAME_SERVERS_FILE)
+        .arg(TOP_LIST_FILE)
+        .arg(k.to_string())
+        .arg(TARGET_DIR)
+        .args(extra)
+        .output()
+        .unwrap()
+}
+
+/// Runs the reader with `args`, relative to `dir`, and returns what it printed.
+pub fn run_reader(dir: &Path, args: &[&str]) -> String {
+    let reader = Command::new(env!("CARGO_BIN_EXE_reader"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(reader.status.success(), "{:?}", reader);
+    String::from_utf8(reader.stdout).unwrap()
+}
@@ -0,0 +1,320 @@
+//! In-process caching resolver stand-in, scripted with the cache state the tests expect a real
+//! resolver to be in. It listens on the same localhost port over UDP and TCP.
+
+use dns_collect::ecs::ClientSubnet;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use trust_dns_proto::op::{Edns, Message, MessageType, ResponseCode};
+use trust_dns_proto::rr::dnssec::rdata::DNSSECRData;
+use trust_dns_proto::rr::rdata::opt::{EdnsCode, EdnsOption};
+use trust_dns_proto::rr::{Name, RData, Record, RecordType};
+
+struct CacheEntry {
+    records: Vec<Record>,
+    inserted: Instant,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<(Name, RecordType), CacheEntry>,
+    upstream: HashMap<(Name, RecordType), Vec<Record>>,
+    rcodes: HashMap<Name, ResponseCode>,
+    slices: HashMap<Name, ClientSubnet>,
+    max_udp_size: Option<usize>,
+    miss_delay: Duration,
+    queries: Vec<ReceivedQuery>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceivedQuery {
+    pub name: Name,
+    pub record_type: RecordType,
+    pub recursion_desired: bool,
+    pub over_tcp: bool,
+    pub client_subnet: Option<ClientSubnet>,
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<CacheState>>,
+}
+
+impl MockServer {
+    pub fn start() -> Self {
+        let (udp, tcp) = loop {
+            let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+            if let Ok(tcp) = TcpListener::bind(udp.local_addr().unwrap()) {
+                break (udp, tcp);
+            }
+        };
+        let addr = udp.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(CacheState::default()));
+
+        let udp_state = state.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            loop {
+                let (len, peer) = match udp.recv_from(&mut buf) {
+                    Ok(v) => v,
+                    Err(_) => continue,
+                };
+                if let Some((bytes, delay)) = handle(&udp_state, &buf[..len], false) {
+                    if delay == Duration::default() {
+                        let _ = udp.send_to(&bytes, peer);
+                    } else {
+                        let udp = udp.try_clone().unwrap();
+                        thread::spawn(move || {
+                            thread::sleep(delay);
+                            let _ = udp.send_to(&bytes, peer);
+                        });
+                    }
+                }
+            }
+        });
+
+        let tcp_state = state.clone();
+        thread::spawn(move || {
+            for socket in tcp.incoming().flatten() {
+                let state = tcp_state.clone();
+                thread::spawn(move || serve_tcp(&state, socket));
+            }
+        });
+
+        Self { addr, state }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Puts `rdata` for `name` in the cache with `ttl` seconds left; the TTL then counts down.
+    pub fn cache(&self, name: &Name, ttl: u32, rdata: RData) {
+        let record = Record::from_rdata(name.clone(), ttl, rdata);
+        let mut state = self.state.lock().unwrap();
+        state
+            .entries
+            .entry((name.clone(), record.record_type()))
+            .or_insert_with(|| CacheEntry {
+                records: Vec::new(),
+                inserted: Instant::now(),
+            })
+            .records
+            .push(record);
+    }
+
+    /// Makes `rdata` resolvable for `name`: a recursive query for it puts it in the cache with the
+    /// full `ttl`.
+    pub fn upstream(&self, name: &Name, ttl: u32, rdata: RData) {
+        let record = Record::from_rdata(name.clone(), ttl, rdata);
+        let mut state = self.state.lock().unwrap();
+        state
+            .upstream
+            .entry((name.clone(), record.record_type()))
+            .or_default()
+            .push(record);
+    }
+
+    /// Shows the cached records of `name` only to queries with `client_subnet` in their EDNS
+    /// Client Subnet option, and answers those with a scope of the full prefix.
+    pub fn cache_in_slice(&self, name: &Name, client_subnet: ClientSubnet) {
+        let mut state = self.state.lock().unwrap();
+        state.slices.insert(name.clone(), client_subnet);
+    }
+
+    /// Answers every query for `name` with `rcode` and no records.
+    pub fn set_rcode(&self, name: &Name, rcode: ResponseCode) {
+        let mut state = self.state.lock().unwrap();
+        state.rcodes.insert(name.clone(), rcode);
+    }
+
+    /// Truncates UDP responses larger than `size` bytes.
+    pub fn set_max_udp_size(&self, size: usize) {
+        self.state.lock().unwrap().max_udp_size = Some(size);
+    }
+
+    /// Holds back responses without answers by `delay`, the way a resolver takes longer when it
+    /// has to recurse.
+    pub fn set_miss_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().miss_delay = delay;
+    }
+
+    pub fn queries(&self) -> Vec<ReceivedQuery> {
+        self.state.lock().unwrap().queries.clone()
+    }
+}
+
+fn serve_tcp(state: &Mutex<CacheState>, mut socket: TcpStream) {
+    loop {
+        let mut len = [0u8; 2];
+        if socket.read_exact(&mut len).is_err() {
+            return;
+        }
+        let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+        if socket.read_exact(&mut buf).is_err() {
+            return;
+        }
+        if let Some((bytes, delay)) = handle(state, &buf, true) {
+            thread::sleep(delay);
+            let mut framed = (bytes.len() as u16).to_be_bytes().to_vec();
+            framed.extend_from_slice(&bytes);
+            if socket.write_all(&framed).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Returns the encoded response and how long to wait before sending it.
+fn handle(state: &Mutex<CacheState>, buf: &[u8], over_tcp: bool) -> Option<(Vec<u8>, Duration)> {
+    let request = Message::from_vec(buf).ok()?;
+    let query = request.queries().first()?.clone();
+    let client_subnet = ClientSubnet::from_message(&request).map(|(v, _)| v);
+    let mut state = state.lock().unwrap();
+    state.queries.push(ReceivedQuery {
+        name: query.name().clone(),
+        record_type: query.query_type(),
+        recursion_desired: request.recursion_desired(),
+        over_tcp,
+        client_subnet,
+    });
+
+    let mut response = Message::new();
+    response
+        .set_id(request.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(request.op_code())
+        .set_recursion_desired(request.recursion_desired())
+        .set_recursion_available(true)
+        .add_query(query.clone());
+    match state.rcodes.get(query.name()) {
+        Some(rcode) => {
+            response.set_response_code(*rcode);
+        }
+        None => {
+            let key = (query.name().clone(), query.query_type());
+            if request.recursion_desired() && !state.entries.contains_key(&key) {
+                if let Some(records) = state.upstream.get(&key).cloned() {
+                    state.entries.insert(
+                        key,
+                        CacheEntry {
+                            records,
+                            inserted: Instant::now(),
+                        },
+                    );
+                }
+            }
+            match state.slices.get(query.name()) {
+                Some(slice) if Some(*slice) != client_subnet => {}
+                _ => {
+                    let dnssec_ok = request.edns().is_some_and(|edns| edns.dnssec_ok());
+                    response.add_answers(lookup(
+                        &state,
+                        query.name(),
+                        query.query_type(),
+                        dnssec_ok,
+                    ));
+                }
+            }
+        }
+    }
+    if let Some(EdnsOption::Unknown(code, data)) = request
+        .edns()
+        .and_then(|edns| edns.option(EdnsCode::Subnet))
+    {
+        let mut data = data.clone();
+        if state.slices.contains_key(query.name()) {
+            data[3] = data[2];
+        }
+        let mut edns = Edns::new();
+        edns.set_option(EdnsOption::Unknown(*code, data));
+        response.set_edns(edns);
+    }
+
+    let delay = if response.answers().is_empty() {
+        state.miss_delay
+    } else {
+        Duration::default()
+    };
+    let bytes = response.to_vec().unwrap();
+    let bytes = match state.max_udp_size {
+        Some(size) if !over_tcp && bytes.len() > size => {
+            let mut truncated = Message::new();
+            truncated
+                .set_id(request.id())
+                .set_message_type(MessageType::Response)
+                .add_query(query);
+            let mut bytes = truncated.to_vec().unwrap();
+            // the encoder recomputes TC from what it had to drop, so set it by hand
+            bytes[2] |= 0b0000_0010;
+            bytes
+        }
+        _ => bytes,
+    };
+    Some((bytes, delay))
+}
+
+/// Returns the cached records for `name`, following cached CNAMEs, along with the cached
+/// signatures over them if the query has the DO bit set.
+fn lookup(
+    state: &CacheState,
+    name: &Name,
+    record_type: RecordType,
+    dnssec_ok: bool,
+) -> Vec<Record> {
+    let mut answers = Vec::new();
+    let mut name = name.clone();
+    // bounded to stay clear of CNAME loops
+    for _ in 0..8 {
+        if let Some(entry) = state.entries.get(&(name.clone(), record_type)) {
+            answers.extend(remaining(entry));
+            if dnssec_ok {
+                answers.extend(signatures(state, &name, record_type));
+            }
+            break;
+        }
+        match state.entries.get(&(name.clone(), RecordType::CNAME)) {
+            Some(entry) if record_type != RecordType::CNAME => {
+                let records = remaining(entry);
+                match records.first().map(Record::rdata) {
+                    Some(RData::CNAME(target)) => name = target.clone(),
+                    _ => break,
+                }
+                answers.extend(records);
+            }
+            _ => break,
+        }
+    }
+    answers
+}
+
+fn signatures(state: &CacheState, name: &Name, record_type: RecordType) -> Vec<Record> {
+    state
+        .entries
+        .iter()
+        .filter(|((owner, _), _)| owner == name)
+        .flat_map(|(_, entry)| remaining(entry))
+        .filter(|record| match record.rdata() {
+            RData::DNSSEC(DNSSECRData::SIG(sig)) => sig.type_covered() == record_type,
+            _ => false,
+        })
+        .collect()
+}
+
+/// Returns the records of `entry` with their TTLs counted down, or nothing if they expired.
+fn remaining(entry: &CacheEntry) -> Vec<Record> {
+    let elapsed = entry.inserted.elapsed().as_secs() as u32;
+    entry
+        .records
+        .iter()
+        .filter(|record| record.ttl() > elapsed)
+        .map(|record| {
+            let mut record = record.clone();
+            record.set_ttl(record.ttl() - elapsed);
+            record
+        })
+        .collect()
+}
@@ -0,0 +1,191 @@
+#![allow(dead_code)]
+
+pub mod crawl;
+pub mod mock_server;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::TlsAcceptor;
+use trust_dns_proto::op::{Message, MessageType};
+use trust_dns_proto::rr::{Name, RData, Record};
+
+pub const ANSWER_ADDR: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+pub const ANSWER_TTL: u32 = 300;
+
+pub fn fqdn(name: &str) -> Name {
+    let mut name = Name::from_str(name).unwrap();
+    name.set_fqdn(true);
+    name
+}
+
+/// Answers every query with a single A record pointing at `ANSWER_ADDR`.
+pub fn answer(request: &Message) -> Message {
+    let mut response = Message::new();
+    response
+        .set_id(request.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(request.op_code())
+        .set_recursion_desired(request.recursion_desired())
+        .add_queries(request.queries().to_vec());
+    for query in request.queries() {
+        response.add_answer(Record::from_rdata(
+            query.name().clone(),
+            ANSWER_TTL,
+            RData::A(ANSWER_ADDR),
+        ));
+    }
+    response
+}
+
+fn serve_framed<S: Read + Write>(stream: &mut S, max_queries: usize) {
+    for _ in 0..max_queries {
+        let mut len = [0u8; 2];
+        if stream.read_exact(&mut len).is_err() {
+            return;
+        }
+        let mut buf = vec![0u8; u16::from_be_bytes(len) as usize];
+        if stream.read_exact(&mut buf).is_err() {
+            return;
+        }
+        let request = Message::from_vec(&buf).unwrap();
+        let bytes = answer(&request).to_vec().unwrap();
+        let mut framed = (bytes.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&bytes);
+        if stream.write_all(&framed).is_err() {
+            return;
+        }
+    }
+}
+
+fn server_config(cert: CertificateDer<'static>, key: Vec<u8>) -> ServerConfig {
+    ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![cert],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key)),
+        )
+        .unwrap()
+}
+
+/// Starts a DNS-over-TLS stand-in on an ephemeral localhost port, which closes each connection
+/// after answering `max_queries` on it. Returns the count of connections it accepted as well.
+pub fn spawn_tls_server(
+    cert: CertificateDer<'static>,
+    key: Vec<u8>,
+    max_queries: usize,
+) -> (SocketAddr, Arc<AtomicUsize>) {
+    let config = Arc::new(server_config(cert, key));
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = connections.clone();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let socket: TcpStream = match socket {
+                Ok(socket) => socket,
+                Err(_) => continue,
+            };
+            accepted.fetch_add(1, Ordering::SeqCst);
+            let config = config.clone();
+            thread::spawn(move || {
+                let connection = ServerConnection::new(config).unwrap();
+                serve_framed(&mut StreamOwned::new(connection, socket), max_queries);
+            });
+        }
+    });
+    (addr, connections)
+}
+
+/// Starts a DNS-over-HTTPS stand-in on an ephemeral localhost port, serving `/dns-query` for both
+/// GET and POST over HTTP/2 only. The ALPN protocol each connection negotiated is added to the
+/// returned list.
+pub fn spawn_https_server(
+    cert: CertificateDer<'static>,
+    key: Vec<u8>,
+) -> (SocketAddr, Arc<Mutex<Vec<Vec<u8>>>>) {
+    let mut config = server_config(cert, key);
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let protocols = Arc::new(Mutex::new(Vec::new()));
+    let negotiated = protocols.clone();
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            loop {
+                let socket = match listener.accept().await {
+                    Ok((socket, _)) => socket,
+                    Err(_) => continue,
+                };
+                let acceptor = acceptor.clone();
+                let negotiated = negotiated.clone();
+                tokio::spawn(async move {
+                    let stream = match acceptor.accept(socket).await {
+                        Ok(stream) => stream,
+                        Err(_) => return,
+                    };
+                    let protocol = stream.get_ref().1.alpn_protocol().unwrap_or_default();
+                    negotiated.lock().unwrap().push(protocol.to_vec());
+                    serve_h2(stream).await;
+                });
+            }
+        });
+    });
+    (addr, protocols)
+}
+
+async fn serve_h2<S: AsyncRead + AsyncWrite + Unpin>(stream: S) {
+    let mut connection = match h2::server::handshake(stream).await {
+        Ok(connection) => connection,
+        Err(_) => return,
+    };
+    while let Some(Ok((request, mut respond))) = connection.accept().await {
+        tokio::spawn(async move {
+            let (parts, mut body) = request.into_parts();
+            let mut bytes = Vec::new();
+            while let Some(Ok(chunk)) = body.data().await {
+                let _ = body.flow_control().release_capacity(chunk.len());
+                bytes.extend_from_slice(&chunk);
+            }
+            let dns = parts.uri.query().and_then(|v| v.strip_prefix("dns="));
+            let request = match (parts.method.as_str(), parts.uri.path(), dns) {
+                ("POST", "/dns-query", None) => Some(bytes),
+                ("GET", "/dns-query", Some(dns)) => URL_SAFE_NO_PAD.decode(dns).ok(),
+                _ => None,
+            };
+            match request.and_then(|r| Message::from_vec(&r).ok()) {
+                Some(request) => {
+                    let response = http::Response::builder()
+                        .header("content-type", "application/dns-message")
+                        .body(())
+                        .unwrap();
+                    if let Ok(mut stream) = respond.send_response(response, false) {
+                        let bytes = answer(&request).to_vec().unwrap();
+                        let _ = stream.send_data(bytes.into(), true);
+                    }
+                }
+                None => {
+                    let response = http::Response::builder().status(400).body(()).unwrap();
+                    let _ = respond.send_response(response, true);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::fqdn;
+use dns_collect::collect::{AllDomains, AllQueries, DomainStat};
+use dns_collect::container::{self, Content, Header, MAGIC};
+use dns_collect::error::ContainerError;
+use dns_collect::record_wrapper::RecordWrapper;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::time::{Duration, SystemTime};
+use trust_dns_proto::rr::{Name, RData, Record};
+
+fn all_domains() -> AllDomains {
+    let name = fqdn("example.com");
+    let record = Record::from_rdata(name.clone(), 300, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+    let stat = DomainStat {
+        counts: 3,
+        ttls: vec![300].into_iter().collect(),
+        ..DomainStat::default()
+    };
+    let mut all_domains = AllDomains::new();
+    all_domains
+        .entry(name)
+        .or_default()
+        .insert(RecordWrapper::new(record), stat);
+    all_domains
+}
+
+fn header() -> Header {
+    Header {
+        record_type: "A".to_owned(),
+        name_server: "A/Google".to_owned(),
+        repeat: 10,
+        first_rank: 1,
+        last_rank: 1000,
+        started_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        finished_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_060),
+        ..Header::new(Content::AllDomains)
+    }
+}
+
+fn written() -> Vec<u8> {
+    let mut bytes = Vec::new();
+    container::write(&mut bytes, &header(), &all_domains()).unwrap();
+    bytes
+}
+
+#[test]
+fn round_trips_header_and_body() {
+    let bytes = written();
+    assert!(bytes.starts_with(&MAGIC));
+    let (read_header, body) = container::read::<AllDomains>(&bytes[..]).unwrap();
+    assert_eq!(read_header, Some(header()));
+    assert_eq!(format!("{:?}", body), format!("{:?}", all_domains()));
+}
+
+#[test]
+fn reads_files_from_before_the_container() {
+    let bytes = bincode::serialize(&all_domains()).unwrap();
+    let (read_header, body) = container::read::<AllDomains>(&bytes[..]).unwrap();
+    assert_eq!(read_header, None);
+    assert_eq!(format!("{:?}", body), format!("{:?}", all_domains()));
+
+    // before TTL observations were kept, a record only had its counts and TTLs
+    #[derive(Serialize)]
+    struct DomainStatV0 {
+        counts: usize,
+        ttls: HashSet<u32>,
+    }
+    let legacy: HashMap<Name, HashMap<RecordWrapper, DomainStatV0>> = all_domains()
+        .into_iter()
+        .map(|(name, records)| {
+            let records = records
+                .into_iter()
+                .map(|(record, stat)| {
+                    let stat = DomainStatV0 {
+                        counts: stat.counts,
+                        ttls: stat.ttls,
+                    };
+                    (record, stat)
+                })
+                .collect();
+            (name, records)
+        })
+        .collect();
+    let bytes = bincode::serialize(&legacy).unwrap();
+    let (_, body) = container::read::<AllDomains>(&bytes[..]).unwrap();
+    assert_eq!(format!("{:?}", body), format!("{:?}", all_domains()));
+}
+
+#[test]
+fn rejects_damaged_or_foreign_files() {
+    let mut bytes = written();
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 0x01;
+    assert!(matches!(
+        container::read::<AllDomains>(&bytes[..]),
+        Err(ContainerError::BadChecksum)
+    ));
+
+    let bytes = written();
+    assert!(matches!(
+        container::read::<AllDomains>(&bytes[..bytes.len() - 10]),
+        Err(ContainerError::BadChecksum)
+    ));
+    assert!(matches!(
+        container::read::<AllDomains>(&MAGIC[..]),
+        Err(ContainerError::Truncated)
+    ));
+
+    let mut bytes = written();
+    bytes[MAGIC.len() + 1] = 2;
+    assert!(matches!(
+        container::read::<AllDomains>(&bytes[..]),
+        Err(ContainerError::UnsupportedVersion(2))
+    ));
+
+    let bytes = written();
+    assert!(matches!(
+        container::read::<AllQueries>(&bytes[..]),
+        Err(ContainerError::WrongContent(Content::AllDomains))
+    ));
+}
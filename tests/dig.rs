@@ -0,0 +1,186 @@
+use dns_collect::dig::{command_args, parse_output};
+use dns_collect::ecs::ClientSubnet;
+use dns_collect::error::RecordParseError;
+use dns_collect::message::build_query;
+use dns_collect::rdata::record_type_name;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::str::FromStr;
+use trust_dns_proto::op::{Message, ResponseCode};
+use trust_dns_proto::rr::{Name, RData, RecordType};
+use trust_dns_proto::serialize::binary::BinEncoder;
+
+const NXDOMAIN: &str = "\
+;; Got answer:
+;; ->>HEADER<<- opcode: QUERY, status: NXDOMAIN, id: 29361
+;; flags: qr aa ra; QUERY: 1, ANSWER: 0, AUTHORITY: 1, ADDITIONAL: 1
+
+;; OPT PSEUDOSECTION:
+; EDNS: version: 0, flags:; udp: 1232
+;; AUTHORITY SECTION:
+example.\t\t3600\tIN\tSOA\tns.example. hostmaster.example. 1 7200 3600 1209600 3600
+";
+
+#[test]
+fn parses_header_and_answer() {
+    let output = "\
+;; Got answer:
+;; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4711
+;; flags: qr ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 1
+
+;; OPT PSEUDOSECTION:
+; EDNS: version: 0, flags:; udp: 1232
+;; ANSWER SECTION:
+example.com.\t\t79\tIN\tA\t192.0.2.1
+";
+    let mut message = Message::new();
+    parse_output(output, &mut message).unwrap();
+    assert_eq!(message.response_code(), ResponseCode::NoError);
+    assert!(message.recursion_available());
+    assert!(!message.recursion_desired());
+    assert!(!message.authoritative());
+    assert_eq!(message.answers().len(), 1);
+    assert_eq!(message.answers()[0].ttl(), 79);
+    assert_eq!(
+        *message.answers()[0].rdata(),
+        RData::A(Ipv4Addr::new(192, 0, 2, 1))
+    );
+}
+
+#[test]
+fn parses_refused_without_records() {
+    let output = "\
+;; Got answer:
+;; ->>HEADER<<- opcode: QUERY, status: REFUSED, id: 1
+;; flags: qr rd; QUERY: 1, ANSWER: 0, AUTHORITY: 0, ADDITIONAL: 0
+;; WARNING: recursion requested but not available
+";
+    let mut message = Message::new();
+    parse_output(output, &mut message).unwrap();
+    assert_eq!(message.response_code(), ResponseCode::Refused);
+    assert!(message.recursion_desired());
+    assert!(!message.recursion_available());
+    assert!(message.answers().is_empty());
+}
+
+#[test]
+fn sorts_authority_records_apart() {
+    let mut message = Message::new();
+    parse_output(NXDOMAIN, &mut message).unwrap();
+    assert_eq!(message.response_code(), ResponseCode::NXDomain);
+    assert!(message.authoritative());
+    assert!(message.answers().is_empty());
+    assert_eq!(message.name_servers().len(), 1);
+}
+
+#[test]
+fn rejects_output_without_header() {
+    let mut message = Message::new();
+    assert!(parse_output(
+        ";; connection timed out; no servers could be reached\n",
+        &mut message
+    )
+    .is_err());
+}
+
+/// Renders what `parse_output` recovered, with the RDATA as wire bytes so the comparison is exact,
+/// followed by the records it skipped.
+fn render(message: &Message, skipped: &[RecordParseError]) -> String {
+    let mut out = format!(
+        "status {:?} aa {} tc {} rd {} ra {}\n",
+        message.response_code(),
+        message.authoritative(),
+        message.truncated(),
+        message.recursion_desired(),
+        message.recursion_available()
+    );
+    let sections = [
+        ("answer", message.answers()),
+        ("authority", message.name_servers()),
+    ];
+    for (section, records) in sections.iter() {
+        for record in records.iter() {
+            let mut wire = Vec::new();
+            record
+                .rdata()
+                .emit(&mut BinEncoder::new(&mut wire))
+                .unwrap();
+            let hex = wire
+                .iter()
+                .map(|v| format!("{:02x}", v))
+                .collect::<String>();
+            out += &format!(
+                "{} {} {} {} {}\n",
+                section,
+                record.name(),
+                record.ttl(),
+                record_type_name(record.rr_type()),
+                hex
+            );
+        }
+    }
+    for e in skipped.iter() {
+        out += &format!("skipped {:?}\n", e);
+    }
+    out
+}
+
+/// Parses every captured `tests/golden/dig/*.out` and compares with the `.expected` next to it.
+/// Run with `UPDATE_GOLDEN=1` to rewrite the expectations after checking the diff.
+#[test]
+fn golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/dig");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let mut checked = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|v| v.to_str()) != Some("out") {
+            continue;
+        }
+        let mut message = Message::new();
+        let skipped = parse_output(&fs::read_to_string(&path).unwrap(), &mut message)
+            .unwrap_or_else(|e| panic!("{}: {:?}", path.display(), e));
+        let actual = render(&message, &skipped);
+        let expected_path = path.with_extension("expected");
+        if update {
+            fs::write(&expected_path, &actual).unwrap();
+        } else {
+            let expected = fs::read_to_string(&expected_path).unwrap();
+            assert_eq!(actual, expected, "{}", path.display());
+        }
+        checked += 1;
+    }
+    assert!(checked > 0);
+}
+
+#[test]
+fn rejects_unterminated_records() {
+    let output = NXDOMAIN.replace("hostmaster.example. 1", "hostmaster.example. ( 1");
+    let mut message = Message::new();
+    assert!(parse_output(&output, &mut message).is_err());
+    let output = NXDOMAIN.replace("hostmaster.example. 1", "\"hostmaster.example. 1");
+    let mut message = Message::new();
+    assert!(parse_output(&output, &mut message).is_err());
+}
+
+#[test]
+fn passes_the_request_on_to_dig() {
+    let mut request = build_query(Name::from_str("example.com.").unwrap(), RecordType::AAAA);
+    let args = command_args("192.0.2.53:5353".parse().unwrap(), &request);
+    assert!(args.contains(&"+norecurse".to_owned()));
+    assert!(args.contains(&"+dnssec".to_owned()));
+    assert!(!args.iter().any(|v| v.starts_with("+subnet")));
+    assert!(args.ends_with(&[
+        "@192.0.2.53".to_owned(),
+        "-p".to_owned(),
+        "5353".to_owned(),
+        "example.com.".to_owned(),
+        "AAAA".to_owned(),
+    ]));
+    ClientSubnet::from_str("198.51.100.0/24")
+        .unwrap()
+        .apply(&mut request);
+    let args = command_args("192.0.2.53:53".parse().unwrap(), &request);
+    assert!(args.contains(&"+subnet=198.51.100.0/24".to_owned()));
+}
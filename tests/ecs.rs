@@ -0,0 +1,54 @@
+use dns_collect::ecs::ClientSubnet;
+use dns_collect::message::build_query;
+use std::net::IpAddr;
+use std::str::FromStr;
+use trust_dns_proto::op::Message;
+use trust_dns_proto::rr::rdata::opt::EdnsOption;
+use trust_dns_proto::rr::{Name, RecordType};
+
+#[test]
+fn parses_and_masks_prefixes() {
+    let subnet = ClientSubnet::from_str("192.0.2.77/24").unwrap();
+    assert_eq!(subnet.addr(), IpAddr::from_str("192.0.2.0").unwrap());
+    assert_eq!(subnet.source_prefix(), 24);
+    assert_eq!(subnet.to_string(), "192.0.2.0/24");
+
+    let subnet = ClientSubnet::from_str("2001:db8:ffff::1/33").unwrap();
+    assert_eq!(subnet.to_string(), "2001:db8:8000::/33");
+
+    assert!(ClientSubnet::from_str("192.0.2.0").is_err());
+    assert!(ClientSubnet::from_str("192.0.2.0/33").is_err());
+    assert!(ClientSubnet::from_str("example/8").is_err());
+}
+
+#[test]
+fn encodes_truncated_address() {
+    let subnet = ClientSubnet::from_str("198.51.100.0/22").unwrap();
+    match subnet.to_option() {
+        EdnsOption::Unknown(code, data) => {
+            assert_eq!(code, 8);
+            assert_eq!(data, vec![0, 1, 22, 0, 198, 51, 100]);
+        }
+        #[allow(unreachable_patterns)]
+        other => panic!("{:?}", other),
+    }
+    let subnet = ClientSubnet::from_str("0.0.0.0/0").unwrap();
+    match subnet.to_option() {
+        EdnsOption::Unknown(_, data) => assert_eq!(data, vec![0, 1, 0, 0]),
+        #[allow(unreachable_patterns)]
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn survives_the_wire() {
+    let subnet = ClientSubnet::from_str("2001:db8::/48").unwrap();
+    let mut request = build_query(Name::from_str("example.com.").unwrap(), RecordType::A);
+    subnet.apply(&mut request);
+    let decoded = Message::from_vec(&request.to_vec().unwrap()).unwrap();
+    assert_eq!(ClientSubnet::from_message(&decoded), Some((subnet, 0)));
+    // the option rides along with the DO bit rather than replacing it
+    assert!(decoded.edns().unwrap().dnssec_ok());
+    let plain = build_query(Name::from_str("example.com.").unwrap(), RecordType::A);
+    assert_eq!(ClientSubnet::from_message(&plain), None);
+}
@@ -0,0 +1,516 @@
+mod common;
+
+use arrow_array::StringArray;
+use common::crawl::{
+    name_server, run_crawler, run_reader, write_name_servers, write_top_list, TARGET_DIR,
+    TOP_LIST_FILE,
+};
+use common::fqdn;
+use common::mock_server::MockServer;
+use dns_collect::collect::{AllDomains, AllQueries};
+use dns_collect::container;
+use dns_collect::ecs::ClientSubnet;
+use dns_collect::observation::read_log;
+use dns_collect::rdata::{parse_record_data, parse_record_type};
+use dns_collect::store::{Store, STORE_FILE};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde_json::json;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use trust_dns_proto::rr::RData;
+
+const NAME_SERVERS: [&str; 3] = ["alpha", "beta", "gamma"];
+
+#[test]
+fn crawl_and_read() {
+    let servers = NAME_SERVERS
+        .iter()
+        .map(|_| MockServer::start())
+        .collect::<Vec<_>>();
+    // "shared.example" is cached everywhere, "only-<name>.example" only on one server
+    for (server, name) in servers.iter().zip(NAME_SERVERS.iter()) {
+        server.cache(
+            &fqdn("shared.example"),
+            300,
+            RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+        );
+        server.cache(
+            &fqdn(&format!("only-{}.example", name)),
+            300,
+            RData::A(Ipv4Addr::new(192, 0, 2, 2)),
+        );
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let name_servers = servers
+        .iter()
+        .zip(NAME_SERVERS.iter())
+        .map(|(server, name)| name_server(name, server))
+        .collect::<Vec<_>>();
+    write_name_servers(dir.path(), &name_servers);
+    let mut domains = vec!["shared.example".to_owned()];
+    domains.extend(NAME_SERVERS.iter().map(|v| format!("only-{}.example", v)));
+    domains.push("uncached.example".to_owned());
+    write_top_list(
+        dir.path(),
+        &domains.iter().map(String::as_str).collect::<Vec<_>>(),
+    );
+    let target_dir = dir.path().join(TARGET_DIR);
+
+    let crawler = run_crawler(dir.path(), "A", 5, &[]);
+    assert!(crawler.status.success(), "{:?}", crawler);
+    for name in NAME_SERVERS.iter() {
+        assert!(target_dir.join("A").join(name).join("1-5.txt").is_file());
+        assert!(target_dir
+            .join("A")
+            .join(name)
+            .join("1-5.outcomes")
+            .is_file());
+    }
+    let chunk = fs::File::open(target_dir.join("A/alpha/1-5.txt")).unwrap();
+    let (header, _) = container::read::<AllDomains>(chunk).unwrap();
+    let header = header.unwrap();
+    assert_eq!(header.name_server, "alpha");
+    assert_eq!((header.first_rank, header.last_rank), (1, 5));
+
+    let stdout = run_reader(dir.path(), &[TARGET_DIR, TOP_LIST_FILE]);
+    for name in NAME_SERVERS.iter() {
+        assert!(stdout.contains(&format!("|{}| = 2", name)), "{}", stdout);
+    }
+    // each pair of servers shares only "shared.example"
+    let pairs = stdout
+        .lines()
+        .filter(|line| !line.contains('(') && line.matches('∩').count() == 1)
+        .collect::<Vec<_>>();
+    assert_eq!(pairs.len(), 6, "{}", stdout);
+    assert!(pairs.iter().all(|line| line.ends_with("= 1")), "{}", stdout);
+    // each server answered its two cached names and left the other three empty
+    for name in NAME_SERVERS.iter() {
+        let line = format!(
+            "{}: answered 2, empty 3, refused 0, servfail 0, nxdomain 0, other 0",
+            name
+        );
+        assert!(stdout.lines().any(|v| v == line), "{}", stdout);
+        let prefix = format!("{}: answered ", name);
+        let line = stdout
+            .lines()
+            .find(|v| v.starts_with(&prefix) && v.contains("ms"));
+        assert!(
+            line.is_some_and(|v| v.contains("(n=2)") && v.contains("(n=3)")),
+            "{}",
+            stdout
+        );
+        let line = format!("{}: chained 0, cached via chain 0", name);
+        assert!(stdout.lines().any(|v| v == line), "{}", stdout);
+        // without DNSSEC in the answers there is nothing to count down from
+        let line = format!(
+            "{}: original ttl known 0/2, fills 0, refills 0 (0.0/h)",
+            name
+        );
+        assert!(stdout.lines().any(|v| v == line), "{}", stdout);
+    }
+}
+
+#[test]
+fn crawl_with_one_name_server() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("cached.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    write_name_servers(dir.path(), &[name_server("mock", &server)]);
+    write_top_list(dir.path(), &["cached.example", "uncached.example"]);
+
+    let crawler = run_crawler(dir.path(), "A", 2, &[]);
+    assert!(crawler.status.success(), "{:?}", crawler);
+
+    let stdout = run_reader(dir.path(), &[TARGET_DIR, TOP_LIST_FILE]);
+    assert!(stdout.contains("|mock| = 1"), "{}", stdout);
+    // nothing to intersect with
+    assert!(!stdout.contains('∩'), "{}", stdout);
+    assert!(
+        stdout
+            .lines()
+            .any(|v| v.starts_with("mock: answered 1, empty 1")),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn crawl_and_compare_ecs_slices() {
+    let server = MockServer::start();
+    let slices = ["192.0.2.0/24", "198.51.100.0/24"];
+    for name in ["shared.example", "sliced.example"].iter() {
+        server.cache(&fqdn(name), 300, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+    }
+    server.cache_in_slice(
+        &fqdn("sliced.example"),
+        ClientSubnet::from_str(slices[1]).unwrap(),
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    write_name_servers(dir.path(), &[name_server("mock", &server)]);
+    write_top_list(dir.path(), &["shared.example", "sliced.example"]);
+    let target_dir = dir.path().join(TARGET_DIR);
+
+    let crawler = run_crawler(dir.path(), "A", 2, &[&slices.join(",")]);
+    assert!(crawler.status.success(), "{:?}", crawler);
+    for slice in slices.iter() {
+        let slice_dir = target_dir
+            .join("A")
+            .join(format!("mock@{}", slice.replace('/', "_")));
+        assert!(slice_dir.join("1-2.txt").is_file());
+    }
+
+    let stdout = run_reader(dir.path(), &[TARGET_DIR, TOP_LIST_FILE]);
+    let line = stdout
+        .lines()
+        .find(|v| v.starts_with("mock: ") && v.contains("in all slices"))
+        .unwrap_or_else(|| panic!("{}", stdout));
+    assert!(line.contains("192.0.2.0/24 1,"), "{}", line);
+    assert!(line.contains("198.51.100.0/24 2,"), "{}", line);
+    assert!(
+        line.contains("in all slices 1, in one slice only 1"),
+        "{}",
+        line
+    );
+    // only the sliced name comes back with a non-zero scope, once per repeat
+    assert!(line.ends_with("scoped answers 10/30"), "{}", line);
+}
+
+#[test]
+fn crawl_several_record_types() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("both.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+    );
+    server.cache(
+        &fqdn("both.example"),
+        300,
+        parse_record_data(&["1", ".", "alpn=h2"], parse_record_type("HTTPS").unwrap()).unwrap(),
+    );
+    server.cache(
+        &fqdn("a-only.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 2)),
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    write_name_servers(dir.path(), &[name_server("mock", &server)]);
+    write_top_list(dir.path(), &["both.example", "a-only.example"]);
+    let target_dir = dir.path().join(TARGET_DIR);
+
+    let crawler = run_crawler(dir.path(), "A,HTTPS", 2, &[]);
+    assert!(crawler.status.success(), "{:?}", crawler);
+    for record_type in ["A", "HTTPS"].iter() {
+        assert!(target_dir.join(record_type).join("mock/1-2.txt").is_file());
+    }
+
+    let stdout = run_reader(dir.path(), &[TARGET_DIR, TOP_LIST_FILE]);
+    let (a, https) = stdout
+        .split_once("##### HTTPS #####")
+        .unwrap_or_else(|| panic!("{}", stdout));
+    assert!(a.starts_with("##### A #####"), "{}", stdout);
+    assert!(a.contains("|mock| = 2"), "{}", stdout);
+    assert!(https.contains("|mock| = 1"), "{}", stdout);
+    assert!(
+        https.contains("mock: answered 1, empty 1, refused 0"),
+        "{}",
+        stdout
+    );
+
+    run_reader(dir.path(), &["export", TARGET_DIR, "records.parquet"]);
+    let parquet = fs::File::open(dir.path().join("records.parquet")).unwrap();
+    let batches = ParquetRecordBatchReaderBuilder::try_new(parquet)
+        .unwrap()
+        .build()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let mut rows = Vec::new();
+    for batch in batches.iter() {
+        let column = |name: &str| {
+            batch
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .clone()
+        };
+        let (name_servers, query_types, rdata) =
+            (column("name_server"), column("query_type"), column("rdata"));
+        for i in 0..batch.num_rows() {
+            rows.push(format!(
+                "{} {} {}",
+                name_servers.value(i),
+                query_types.value(i),
+                rdata.value(i)
+            ));
+        }
+    }
+    rows.sort();
+    assert_eq!(
+        rows,
+        [
+            "mock A 192.0.2.1",
+            "mock A 192.0.2.2",
+            "mock HTTPS 1 . alpn=h2",
+        ]
+    );
+
+    let mut lines = run_reader(dir.path(), &["export", "--format", "jsonl", TARGET_DIR])
+        .lines()
+        .map(|line| {
+            let row: serde_json::Value = serde_json::from_str(line).unwrap();
+            format!(
+                "{} {} {}",
+                row["name_server"].as_str().unwrap(),
+                row["query_type"].as_str().unwrap(),
+                row["rdata"].as_str().unwrap()
+            )
+        })
+        .collect::<Vec<_>>();
+    lines.sort();
+    assert_eq!(lines, rows);
+}
+
+#[test]
+fn crawl_with_ttl_baseline() {
+    let resolver = MockServer::start();
+    let authoritative = MockServer::start();
+    // remaining TTLs as the resolver hands them out, against 300s at the source
+    for (name, ttl) in [
+        ("fresh.example", 300),
+        ("old.example", 20),
+        ("long.example", 600),
+    ]
+    .iter()
+    {
+        resolver.cache(&fqdn(name), *ttl, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+        authoritative.cache(&fqdn(name), 300, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut baseline = name_server("auth", &authoritative);
+    baseline["baseline"] = json!(true);
+    write_name_servers(dir.path(), &[name_server("mock", &resolver), baseline]);
+    write_top_list(
+        dir.path(),
+        &["fresh.example", "old.example", "long.example"],
+    );
+    let target_dir = dir.path().join(TARGET_DIR);
+
+    let crawler = run_crawler(dir.path(), "A", 3, &[]);
+    assert!(crawler.status.success(), "{:?}", crawler);
+    let auth_dir = target_dir.join("A").join("auth");
+    assert!(auth_dir.join("1-3.baseline").is_file());
+    assert!(!auth_dir.join("1-3.txt").exists());
+    // one recursive query per name, no repeats
+    assert_eq!(authoritative.queries().len(), 3);
+
+    let stdout = run_reader(dir.path(), &[TARGET_DIR, TOP_LIST_FILE]);
+    assert!(!stdout.contains("|auth|"), "{}", stdout);
+    assert!(stdout.contains("|mock| = 3"), "{}", stdout);
+    assert!(!stdout.contains('∩'), "{}", stdout);
+    assert!(
+        stdout.lines().any(|v| v
+            == "mock: with baseline 3/3, fresh 2, nearly expired 1, stretched 1, capped at 600s 0"),
+        "{}",
+        stdout
+    );
+    // the stretched TTL cannot be counted down from the original one
+    assert!(
+        stdout
+            .lines()
+            .any(|v| v.starts_with("mock: original ttl known 3/3, fills 2, refills 0")),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn crawl_into_observation_log() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("cached.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    write_name_servers(dir.path(), &[name_server("mock", &server)]);
+    write_top_list(dir.path(), &["cached.example", "uncached.example"]);
+    let target_dir = dir.path().join(TARGET_DIR);
+
+    let crawler = run_crawler(dir.path(), "A", 2, &["--observation-log"]);
+    assert!(crawler.status.success(), "{:?}", crawler);
+    let mock_dir = target_dir.join("A").join("mock");
+    assert!(!mock_dir.join("1-2.txt").exists());
+    let observations = read_log(&mock_dir.join("observations.log")).unwrap();
+    // every repeat logs the cached record and the miss
+    assert_eq!(observations.len(), 2 * 10);
+    assert!(observations
+        .iter()
+        .all(|v| v.name_server == "mock" && v.client_subnet.is_none()));
+
+    let stdout = run_reader(dir.path(), &[TARGET_DIR, TOP_LIST_FILE]);
+    assert!(stdout.contains("|mock| = 1"), "{}", stdout);
+}
+
+#[test]
+fn resume_interrupted_crawl() {
+    let servers = [MockServer::start(), MockServer::start()];
+    for server in servers.iter() {
+        server.cache(
+            &fqdn("cached.example"),
+            300,
+            RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+        );
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let name_servers = [
+        name_server("mock", &servers[0]),
+        name_server("other", &servers[1]),
+    ];
+    write_name_servers(dir.path(), &name_servers);
+    write_top_list(dir.path(), &["cached.example", "uncached.example"]);
+    let target_dir = dir.path().join(TARGET_DIR);
+
+    let crawler = run_crawler(dir.path(), "A", 2, &["--observation-log"]);
+    assert!(crawler.status.success(), "{:?}", crawler);
+    let mock_dir = target_dir.join("A").join("mock");
+    // the crawler died while saving the chunk of "mock"
+    let outcomes = mock_dir.join("1-2.outcomes");
+    let bytes = fs::read(&outcomes).unwrap();
+    fs::write(&outcomes, &bytes[..bytes.len() / 2]).unwrap();
+
+    let crawler = run_crawler(dir.path(), "A", 2, &["--observation-log"]);
+    let stderr = String::from_utf8(crawler.stderr).unwrap();
+    assert!(stderr.contains("already holds a crawl"), "{}", stderr);
+    let crawler = run_crawler(dir.path(), "A", 1, &["--observation-log", "--resume"]);
+    let stderr = String::from_utf8(crawler.stderr).unwrap();
+    assert!(stderr.contains("Mismatch(\"domain list\")"), "{}", stderr);
+    let mut changed = name_servers.clone();
+    changed[0]["differential"] = json!(true);
+    write_name_servers(dir.path(), &changed);
+    let crawler = run_crawler(dir.path(), "A", 2, &["--observation-log", "--resume"]);
+    let stderr = String::from_utf8(crawler.stderr).unwrap();
+    assert!(stderr.contains("Mismatch(\"name servers\")"), "{}", stderr);
+    write_name_servers(dir.path(), &name_servers);
+
+    let crawler = run_crawler(dir.path(), "A", 2, &["--observation-log", "--resume"]);
+    assert!(crawler.status.success(), "{:?}", crawler);
+    let stderr = String::from_utf8(crawler.stderr).unwrap();
+    assert!(stderr.contains("mock: resuming at chunk 1/1"), "{}", stderr);
+    assert!(
+        stderr.contains("other: all chunks done already"),
+        "{}",
+        stderr
+    );
+    let (header, _) = container::read::<AllQueries>(fs::File::open(&outcomes).unwrap()).unwrap();
+    assert_eq!(header.unwrap().last_rank, 2);
+    // the observations of the unfinished chunk are not counted twice
+    for name in ["mock", "other"].iter() {
+        let log = target_dir.join("A").join(name).join("observations.log");
+        assert_eq!(read_log(&log).unwrap().len(), 2 * 10);
+    }
+}
+
+#[test]
+fn resume_after_budget_runs_out() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("cached.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    write_top_list(dir.path(), &["cached.example", "uncached.example"]);
+    let target_dir = dir.path().join(TARGET_DIR);
+    let crawl = |flags: &[&str], query_budget: usize| {
+        let mut mock = name_server("mock", &server);
+        mock["query_budget"] = json!(query_budget);
+        write_name_servers(dir.path(), &[mock]);
+        run_crawler(dir.path(), "A", 2, flags)
+    };
+
+    // 2 names × 10 repeats do not fit in 15 queries
+    let crawler = crawl(&[], 15);
+    assert!(crawler.status.success(), "{:?}", crawler);
+    let stderr = String::from_utf8(crawler.stderr).unwrap();
+    assert!(stderr.contains("query budget spent"), "{}", stderr);
+    let outcomes = target_dir.join("A/mock/1-2.outcomes");
+    assert!(!outcomes.exists());
+
+    let crawler = crawl(&["--resume"], 100);
+    assert!(crawler.status.success(), "{:?}", crawler);
+    let stderr = String::from_utf8(crawler.stderr).unwrap();
+    assert!(stderr.contains("mock: resuming at chunk 1/1"), "{}", stderr);
+    let (_, all_queries) =
+        container::read::<AllQueries>(fs::File::open(&outcomes).unwrap()).unwrap();
+    assert_eq!(all_queries.len(), 2);
+    for stat in all_queries.values() {
+        assert_eq!(stat.outcomes.values().sum::<usize>(), 10);
+    }
+}
+
+#[test]
+fn crawl_into_sqlite() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("cached.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    write_name_servers(dir.path(), &[name_server("mock", &server)]);
+    write_top_list(dir.path(), &["cached.example", "uncached.example"]);
+    let target_dir = dir.path().join(TARGET_DIR);
+
+    let crawler = run_crawler(dir.path(), "A", 2, &["--sqlite"]);
+    assert!(crawler.status.success(), "{:?}", crawler);
+    let store_path = target_dir.join(STORE_FILE);
+    assert!(!target_dir.join("A/mock/1-2.txt").exists());
+    let store = Store::open(&store_path).unwrap();
+    let all_domains = store.read_domains("A", "mock").unwrap();
+    assert_eq!(all_domains[&fqdn("cached.example")].len(), 1);
+
+    let crawler = run_crawler(dir.path(), "A", 2, &["--resume"]);
+    let stderr = String::from_utf8(crawler.stderr).unwrap();
+    assert!(stderr.contains("Mismatch(\"store\")"), "{}", stderr);
+    let crawler = run_crawler(dir.path(), "A", 2, &["--sqlite", "--resume"]);
+    let stderr = String::from_utf8(crawler.stderr).unwrap();
+    assert!(
+        stderr.contains("mock: all chunks done already"),
+        "{}",
+        stderr
+    );
+
+    let store_source = format!("{}/{}", TARGET_DIR, STORE_FILE);
+    for source in [TARGET_DIR, &store_source].iter() {
+        let stdout = run_reader(dir.path(), &[source, TOP_LIST_FILE]);
+        assert!(stdout.contains("##### A #####"), "{}", stdout);
+        assert!(stdout.contains("|mock| = 1"), "{}", stdout);
+    }
+
+    let stdout = run_reader(dir.path(), &["export", &store_source]);
+    let rows = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(rows.len(), 1, "{}", stdout);
+    assert_eq!(rows[0]["name"], "cached.example.");
+    assert_eq!(rows[0]["query_type"], "A");
+    assert_eq!(rows[0]["counts"], 10);
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::fqdn;
+use dns_collect::collect::{AllDomains, DomainStat};
+use dns_collect::export::{rows, CsvExport, Export, JsonLinesExport, RecordRow};
+use dns_collect::record_wrapper::RecordWrapper;
+use std::net::Ipv4Addr;
+use trust_dns_proto::rr::rdata::MX;
+use trust_dns_proto::rr::{RData, Record};
+
+fn all_domains() -> AllDomains {
+    let name = fqdn("example.com");
+    let mut all_domains = AllDomains::new();
+    let records = [
+        RData::MX(MX::new(10, fqdn("mx.example.com"))),
+        RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+    ];
+    for rdata in records.iter() {
+        let record = Record::from_rdata(name.clone(), 300, rdata.clone());
+        let stat = DomainStat {
+            counts: 3,
+            ttls: vec![300, 120, 240].into_iter().collect(),
+            ..DomainStat::default()
+        };
+        all_domains
+            .entry(name.clone())
+            .or_default()
+            .insert(RecordWrapper::new(record), stat);
+    }
+    all_domains
+}
+
+#[test]
+fn flattens_records_in_presentation_form() {
+    let rows = rows("Google", Some("A"), &all_domains());
+    let a = RecordRow {
+        name_server: "Google".to_owned(),
+        query_type: Some("A".to_owned()),
+        name: "example.com.".to_owned(),
+        record_type: "A".to_owned(),
+        class: "IN".to_owned(),
+        rdata: "192.0.2.1".to_owned(),
+        counts: 3,
+        min_ttl: Some(120),
+        max_ttl: Some(300),
+        original_ttl: None,
+        ttls: vec![120, 240, 300],
+    };
+    let mx = RecordRow {
+        record_type: "MX".to_owned(),
+        rdata: "10 mx.example.com.".to_owned(),
+        ..a.clone()
+    };
+    assert_eq!(rows, vec![a, mx]);
+}
+
+#[test]
+fn writes_json_lines_and_csv() {
+    let rows = rows("Google", None, &all_domains());
+
+    let mut jsonl = Vec::new();
+    let mut export = Box::new(JsonLinesExport::new(&mut jsonl));
+    export.write(&rows).unwrap();
+    export.close().unwrap();
+    let jsonl = String::from_utf8(jsonl).unwrap();
+    let lines = jsonl.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(
+        lines[1],
+        r#"{"name_server":"Google","query_type":null,"name":"example.com.","record_type":"MX","class":"IN","rdata":"10 mx.example.com.","counts":3,"min_ttl":120,"max_ttl":300,"original_ttl":null,"ttls":[120,240,300]}"#
+    );
+
+    let mut csv = Vec::new();
+    let mut export = Box::new(CsvExport::new(&mut csv).unwrap());
+    export.write(&rows).unwrap();
+    export.close().unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "name_server,query_type,name,record_type,class,rdata,counts,min_ttl,max_ttl,original_ttl,ttls\n\
+         Google,,example.com.,A,IN,192.0.2.1,3,120,300,,120 240 300\n\
+         Google,,example.com.,MX,IN,10 mx.example.com.,3,120,300,,120 240 300\n"
+    );
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{fqdn, spawn_https_server, ANSWER_ADDR};
+use dns_collect::collect::{collect, AllDomains, AllQueries};
+use dns_collect::name_server::NameServer;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use trust_dns_proto::rr::{RData, RecordType};
+
+const SERVER_NAME: &str = "dns.test";
+
+/// A DNS-over-HTTPS name server at `path` of a stand-in whose certificate is trusted through
+/// `tls_ca_file`, along with the ALPN protocols the stand-in negotiated.
+fn https_name_server(
+    dir: &TempDir,
+    path: &str,
+    method: &str,
+) -> (NameServer, Arc<Mutex<Vec<Vec<u8>>>>) {
+    let rcgen::CertifiedKey { cert, signing_key } =
+        rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_owned()]).unwrap();
+    let ca_file = dir.path().join("ca.pem");
+    std::fs::write(&ca_file, cert.pem()).unwrap();
+    let (addr, protocols) = spawn_https_server(cert.der().clone(), signing_key.serialize_der());
+    let name_server = serde_json::from_value(json!({
+        "name": "local-doh",
+        "host": "127.0.0.1",
+        "port": addr.port(),
+        "transport": "https",
+        "url": format!("https://{}:{}{}", SERVER_NAME, addr.port(), path),
+        "http_method": method,
+        "tls_ca_file": ca_file,
+    }))
+    .unwrap();
+    (name_server, protocols)
+}
+
+async fn collect_with_method(method: &str) {
+    let dir = tempfile::tempdir().unwrap();
+    let (name_server, protocols) = https_name_server(&dir, "/dns-query{?dns}", method);
+    let domain_names = vec!["example.com".to_owned(), "example.net".to_owned()];
+    let mut all_domains = AllDomains::new();
+    let meta = collect(
+        &name_server,
+        &domain_names,
+        RecordType::A,
+        2,
+        &mut all_domains,
+        &mut AllQueries::new(),
+    )
+    .await;
+    assert_eq!(meta.repeat_valid, 2);
+    assert_eq!(meta.response_valid, 4);
+    for domain_name in domain_names.iter() {
+        let records = &all_domains[&fqdn(domain_name)];
+        let (record, stat) = records.iter().next().unwrap();
+        assert_eq!(record.clone().unwrap().rdata(), &RData::A(ANSWER_ADDR));
+        assert_eq!(stat.counts, 2);
+    }
+    let protocols = protocols.lock().unwrap();
+    assert!(!protocols.is_empty());
+    assert!(protocols.iter().all(|v| v == b"h2"));
+}
+
+#[tokio::test]
+async fn collect_over_https_post() {
+    collect_with_method("POST").await;
+}
+
+#[tokio::test]
+async fn collect_over_https_get() {
+    collect_with_method("GET").await;
+}
+
+#[tokio::test]
+async fn collect_over_https_bad_path() {
+    let dir = tempfile::tempdir().unwrap();
+    let (name_server, _) = https_name_server(&dir, "/resolve", "POST");
+    let mut all_domains = AllDomains::new();
+    let meta = collect(
+        &name_server,
+        &["example.com".to_owned()],
+        RecordType::A,
+        1,
+        &mut all_domains,
+        &mut AllQueries::new(),
+    )
+    .await;
+    assert_eq!(meta.repeat_valid, 0);
+    assert!(all_domains.is_empty());
+}
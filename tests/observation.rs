@@ -0,0 +1,138 @@
+mod common;
+
+use common::fqdn;
+use common::mock_server::MockServer;
+use dns_collect::collect::{AllDomains, AllQueries, Collector};
+use dns_collect::name_server::NameServer;
+use dns_collect::observation::{aggregate, read_log, ObservationLog};
+use dns_collect::rdata::{parse_record_data, parse_record_type};
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::Ipv4Addr;
+use trust_dns_proto::op::ResponseCode;
+use trust_dns_proto::rr::{RData, RecordType};
+
+fn collector(server: &MockServer) -> Collector {
+    let name_server: NameServer = serde_json::from_value(json!({
+        "name": "mock",
+        "host": "127.0.0.1",
+        "port": server.addr().port(),
+    }))
+    .unwrap();
+    Collector::new(&name_server).unwrap()
+}
+
+#[tokio::test]
+async fn logs_every_answer_and_miss() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("www.example"),
+        300,
+        RData::CNAME(fqdn("cdn.example.net")),
+    );
+    server.cache(
+        &fqdn("cdn.example.net"),
+        60,
+        RData::A(Ipv4Addr::new(192, 0, 2, 20)),
+    );
+    let rrsig = "A 13 3 3600 20240201000000 20240101000000 2371 example.net. \
+                 kgd3WTPJ6Vj9A6ymPwJt4Qsx3Xp8Tn3Ww4yGXF7y1Ko="
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    server.cache(
+        &fqdn("cdn.example.net"),
+        60,
+        parse_record_data(&rrsig, parse_record_type("RRSIG").unwrap()).unwrap(),
+    );
+    server.set_rcode(&fqdn("refused.example"), ResponseCode::Refused);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("observations.log");
+    let mut collector = collector(&server);
+    collector.set_observation_log(Some(ObservationLog::open(&path, "mock").unwrap()));
+    let domain_names = ["www.example", "refused.example", "uncached.example"]
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    let mut all_domains = AllDomains::new();
+    collector
+        .collect(
+            &domain_names,
+            RecordType::A,
+            3,
+            &mut all_domains,
+            &mut AllQueries::new(),
+        )
+        .await;
+
+    let observations = read_log(&path).unwrap();
+    // the CNAME, A and RRSIG for www, one empty entry for each of the others, per repeat
+    assert_eq!(observations.len(), 3 * 5);
+    assert!(observations.iter().all(|v| v.name_server == "mock"));
+    assert!(observations
+        .windows(2)
+        .all(|v| v[0].observed_at <= v[1].observed_at));
+    let refused = observations
+        .iter()
+        .filter(|v| v.query_name == fqdn("refused.example"))
+        .collect::<Vec<_>>();
+    assert_eq!(refused.len(), 3);
+    assert!(refused
+        .iter()
+        .all(|v| v.record.is_none() && v.response_code == u16::from(ResponseCode::Refused)));
+
+    // the aggregate is a view of the log
+    let mut derived = AllDomains::new();
+    aggregate(&observations, &mut derived);
+    assert_eq!(derived.len(), all_domains.len());
+    for (name, records) in all_domains.iter() {
+        for (record, stat) in records.iter() {
+            let derived = &derived[name][record];
+            assert_eq!(derived.counts, stat.counts);
+            assert_eq!(derived.ttls, stat.ttls);
+            assert_eq!(derived.original_ttl, stat.original_ttl);
+        }
+    }
+    let signed = derived[&fqdn("cdn.example.net")]
+        .iter()
+        .filter(|(_, stat)| stat.original_ttl == Some(3600))
+        .count();
+    assert_eq!(signed, 1);
+}
+
+#[tokio::test]
+async fn appends_and_ignores_a_cut_off_entry() {
+    let server = MockServer::start();
+    server.cache(
+        &fqdn("cached.example"),
+        300,
+        RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("observations.log");
+    for client_subnet in [None, Some("192.0.2.0/24")] {
+        let mut collector = collector(&server);
+        collector.set_client_subnet(client_subnet.map(|v| v.parse().unwrap()));
+        collector.set_observation_log(Some(ObservationLog::open(&path, "mock").unwrap()));
+        collector
+            .collect(
+                &["cached.example".to_owned()],
+                RecordType::A,
+                1,
+                &mut AllDomains::new(),
+                &mut AllQueries::new(),
+            )
+            .await;
+    }
+    let observations = read_log(&path).unwrap();
+    assert_eq!(observations.len(), 2);
+    assert_eq!(observations[0].client_subnet, None);
+    assert_eq!(
+        observations[1].client_subnet.as_deref(),
+        Some("192.0.2.0/24")
+    );
+    // a crash in the middle of an entry
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[0, 0, 1, 0, 42]).unwrap();
+    assert_eq!(read_log(&path).unwrap().len(), 2);
+}
@@ -0,0 +1,55 @@
+use dns_collect::presentation::{parse_name, split_unescaped, tokenize, unescape, Token};
+
+fn texts(line: &[Token]) -> Vec<&str> {
+    line.iter().map(|v| v.text.as_str()).collect()
+}
+
+#[test]
+fn tokenizes_quotes_parentheses_and_comments() {
+    let lines = tokenize(
+        "a. 60 IN TXT \"x ; y\" z ; comment\n\
+         b. 60 IN SOA ns. host. ( 1 ; serial\n  2 3 4 5 )\n\
+         c. 60 IN HTTPS 1 . alpn=\"h2,h3\" \"\"\n",
+    )
+    .unwrap();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(texts(&lines[0]), ["a.", "60", "IN", "TXT", "x ; y", "z"]);
+    assert!(lines[0][4].quoted);
+    assert!(!lines[0][5].quoted);
+    assert_eq!(
+        texts(&lines[1]),
+        ["b.", "60", "IN", "SOA", "ns.", "host.", "1", "2", "3", "4", "5"]
+    );
+    assert_eq!(texts(&lines[2])[6..], ["alpn=h2,h3", ""]);
+}
+
+#[test]
+fn keeps_escapes_for_later() {
+    let lines = tokenize("a\\ b. \"q\\\"uote\" \\(x\\)").unwrap();
+    assert_eq!(texts(&lines[0]), ["a\\ b.", "q\\\"uote", "\\(x\\)"]);
+    assert_eq!(unescape("q\\\"uote").unwrap(), b"q\"uote");
+    assert_eq!(unescape("caf\\195\\169").unwrap(), "café".as_bytes());
+    assert!(unescape("\\999").is_err());
+    assert_eq!(split_unescaped("a\\,b,c", ','), ["a\\,b", "c"]);
+}
+
+#[test]
+fn reports_unbalanced_input() {
+    assert!(tokenize("a. 60 IN TXT \"open").is_err());
+    assert!(tokenize("a. 60 IN SOA ( 1 2").is_err());
+    assert!(tokenize("a. 60 IN A 192.0.2.1 )").is_err());
+    assert!(tokenize("a. 60 IN TXT x\\").is_err());
+}
+
+#[test]
+fn parses_names_with_escaped_labels() {
+    let name = parse_name("a\\.b.example.").unwrap();
+    assert!(name.is_fqdn());
+    assert_eq!(name.num_labels(), 2);
+    assert_eq!(
+        parse_name("edge\\032node.example.").unwrap(),
+        parse_name("edge\\ node.example.").unwrap()
+    );
+    assert!(!parse_name("relative").unwrap().is_fqdn());
+    assert!(parse_name("a..b.").is_err());
+}
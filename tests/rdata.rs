@@ -0,0 +1,262 @@
+use dns_collect::presentation::tokenize;
+use dns_collect::rdata::{
+    format_record_data, parse_record_data, parse_record_type, record_type_name, HTTPS, SVCB,
+};
+use dns_collect::record_wrapper::RecordWrapper;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use trust_dns_proto::rr::dnssec::rdata::{DNSSECRData, DNSSECRecordType};
+use trust_dns_proto::rr::dnssec::{Algorithm, DigestType};
+use trust_dns_proto::rr::rdata::caa::{Property, Value};
+use trust_dns_proto::rr::{Name, RData, Record, RecordType};
+use trust_dns_proto::serialize::binary::{BinDecodable, BinEncodable};
+
+fn parse(record_type: &str, rdata: &str) -> RData {
+    let tokens = rdata.split_ascii_whitespace().collect::<Vec<_>>();
+    parse_record_data(&tokens, parse_record_type(record_type).unwrap()).unwrap()
+}
+
+/// Sends `rdata` through the wire format and checks it comes back as the same record.
+fn assert_round_trip(rdata: &RData) {
+    let record = Record::from_rdata(Name::from_str("example.com.").unwrap(), 300, rdata.clone());
+    let decoded = Record::from_bytes(&record.to_bytes().unwrap()).unwrap();
+    assert_eq!(decoded.rdata(), rdata);
+    assert!(RecordWrapper::new(decoded) == RecordWrapper::new(record));
+}
+
+fn name(s: &str) -> Name {
+    Name::from_str(s).unwrap()
+}
+
+#[test]
+fn parses_mx_soa_srv() {
+    match parse("MX", "10 mx.example.com.") {
+        RData::MX(ref mx) => {
+            assert_eq!(mx.preference(), 10);
+            assert_eq!(*mx.exchange(), name("mx.example.com."));
+        }
+        other => panic!("{:?}", other),
+    }
+    let soa = parse(
+        "SOA",
+        "ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300",
+    );
+    match soa {
+        RData::SOA(ref soa) => {
+            assert_eq!(*soa.mname(), name("ns1.example.com."));
+            assert_eq!(soa.serial(), 2024010101);
+            assert_eq!(soa.expire(), 1209600);
+            assert_eq!(soa.minimum(), 300);
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert_round_trip(&soa);
+    let srv = parse("SRV", "0 5 5060 sip.example.com.");
+    match srv {
+        RData::SRV(ref srv) => {
+            assert_eq!(srv.weight(), 5);
+            assert_eq!(srv.port(), 5060);
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert_round_trip(&srv);
+}
+
+#[test]
+fn parses_txt_and_caa() {
+    let tokens = ["v=spf1 -all", "second string"];
+    let txt = parse_record_data(&tokens, RecordType::TXT).unwrap();
+    match txt {
+        RData::TXT(ref txt) => {
+            assert_eq!(txt.txt_data().len(), 2);
+            assert_eq!(&*txt.txt_data()[0], b"v=spf1 -all");
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert_round_trip(&txt);
+
+    let caa = parse("CAA", "0 issue letsencrypt.org");
+    match caa {
+        RData::CAA(ref caa) => {
+            assert!(!caa.issuer_critical());
+            assert_eq!(*caa.tag(), Property::Issue);
+            assert_eq!(
+                *caa.value(),
+                Value::Issuer(Some(name("letsencrypt.org")), Vec::new())
+            );
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert_round_trip(&caa);
+}
+
+#[test]
+fn parses_dnssec_types() {
+    let ds = parse("DS", "60485 5 1 2BB183AF5F22588179A53B0A 98631FAD1A292118");
+    match ds {
+        RData::DNSSEC(DNSSECRData::DS(ref ds)) => {
+            assert_eq!(ds.key_tag(), 60485);
+            assert_eq!(ds.algorithm(), Algorithm::RSASHA1);
+            assert_eq!(ds.digest_type(), DigestType::SHA1);
+            assert_eq!(ds.digest().len(), 20);
+            assert_eq!(ds.digest()[..2], [0x2b, 0xb1]);
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert_round_trip(&ds);
+
+    let dnskey = parse(
+        "DNSKEY",
+        "257 3 13 mdsswUyr3DPW132mOi8V9xESWE8jTo0dxCjjnopKl+GqJxpVXckHAeF+ \
+         KkxLbxILfDLUT0rAK9iUzy1L53eKGQ==",
+    );
+    match dnskey {
+        RData::DNSSEC(DNSSECRData::DNSKEY(ref dnskey)) => {
+            assert!(dnskey.zone_key());
+            assert!(dnskey.secure_entry_point());
+            assert_eq!(dnskey.algorithm(), Algorithm::ECDSAP256SHA256);
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert_round_trip(&dnskey);
+
+    let rrsig = parse(
+        "RRSIG",
+        "A 13 2 300 20240201000000 1704067200 34505 example.com. \
+         kgd3WTPJ6Vj9A6ymPwJt4Qsx3Xp8Tn3Ww4yGXF7y1Ko=",
+    );
+    match rrsig {
+        RData::DNSSEC(DNSSECRData::SIG(ref sig)) => {
+            assert_eq!(sig.type_covered(), RecordType::A);
+            assert_eq!(sig.num_labels(), 2);
+            assert_eq!(sig.original_ttl(), 300);
+            assert_eq!(sig.sig_expiration(), 1706745600);
+            assert_eq!(sig.sig_inception(), 1704067200);
+            assert_eq!(sig.key_tag(), 34505);
+            assert_eq!(*sig.signer_name(), name("example.com."));
+            assert_eq!(sig.sig().len(), 32);
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert_round_trip(&rrsig);
+}
+
+#[test]
+fn encodes_svcb_params_in_key_order() {
+    // RFC 9460 D.2, figure 7
+    let svcb = parse(
+        "SVCB",
+        "16 foo.example.org. alpn=h2,h3-19 mandatory=ipv4hint,alpn ipv4hint=192.0.2.1",
+    );
+    let mut expected = vec![0x00, 0x10];
+    expected.extend_from_slice(b"\x03foo\x07example\x03org\x00");
+    expected.extend_from_slice(&[0x00, 0x00, 0x00, 0x04, 0x00, 0x01, 0x00, 0x04]);
+    expected.extend_from_slice(&[0x00, 0x01, 0x00, 0x09, 0x02]);
+    expected.extend_from_slice(b"h2\x05h3-19");
+    expected.extend_from_slice(&[0x00, 0x04, 0x00, 0x04, 192, 0, 2, 1]);
+    match svcb {
+        RData::Unknown { code, ref rdata } => {
+            assert_eq!(code, SVCB);
+            assert_eq!(rdata.anything(), Some(&expected[..]));
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert_round_trip(&svcb);
+
+    let https = parse("HTTPS", "1 . alpn=h3,h2 port=8443 no-default-alpn");
+    match https {
+        RData::Unknown { code, ref rdata } => {
+            assert_eq!(code, HTTPS);
+            let wire = rdata.anything().unwrap();
+            assert_eq!(wire[..3], [0x00, 0x01, 0x00]);
+            // alpn, no-default-alpn, port
+            assert_eq!(wire[3..5], [0x00, 0x01]);
+            assert_eq!(wire[13..17], [0x00, 0x02, 0x00, 0x00]);
+            assert_eq!(wire[17..], [0x00, 0x03, 0x00, 0x02, 0x20, 0xfb]);
+        }
+        ref other => panic!("{:?}", other),
+    }
+    assert!(parse_record_data(&["1", ".", "port=http"], RecordType::Unknown(HTTPS)).is_err());
+}
+
+#[test]
+fn parses_generic_rdata() {
+    assert_eq!(
+        parse("A", "\\# 4 c0000201"),
+        RData::A(Ipv4Addr::new(192, 0, 2, 1))
+    );
+    match parse("TYPE65", "\\# 3 000100") {
+        RData::Unknown { code, rdata } => {
+            assert_eq!(code, 65);
+            assert_eq!(rdata.anything(), Some(&[0u8, 1, 0][..]));
+        }
+        other => panic!("{:?}", other),
+    }
+    let tokens = ["\\#", "4", "c00002"];
+    assert!(parse_record_data(&tokens, RecordType::A).is_err());
+}
+
+#[test]
+fn names_record_types() {
+    assert_eq!(parse_record_type("https").unwrap(), RecordType::Unknown(65));
+    assert_eq!(
+        parse_record_type("DS").unwrap(),
+        RecordType::DNSSEC(DNSSECRecordType::DS)
+    );
+    assert_eq!(parse_record_type("TYPE1").unwrap(), RecordType::A);
+    assert!(parse_record_type("NOPE").is_err());
+    assert_eq!(record_type_name(RecordType::Unknown(SVCB)), "SVCB");
+    assert_eq!(record_type_name(RecordType::Unknown(4242)), "TYPE4242");
+    assert_eq!(record_type_name(RecordType::MX), "MX");
+}
+
+#[test]
+fn reports_missing_and_bad_fields() {
+    assert!(parse_record_data(&["10"], RecordType::MX).is_err());
+    assert!(parse_record_data(&["ten", "mx.example.com."], RecordType::MX).is_err());
+    assert!(parse_record_data(&["1", "5", "1", "xyz"], parse_record_type("DS").unwrap()).is_err());
+    let tag = "t".repeat(256);
+    assert!(parse_record_data(&["0", &tag, "\"v\""], parse_record_type("CAA").unwrap()).is_err());
+    let svcb = parse_record_type("SVCB").unwrap();
+    let alpn = format!("alpn={}", "a".repeat(256));
+    assert!(parse_record_data(&["1", "svc.example.com.", &alpn], svcb).is_err());
+    let key = format!("key65000={}", "v".repeat(65536));
+    assert!(parse_record_data(&["1", "svc.example.com.", &key], svcb).is_err());
+}
+
+#[test]
+fn formats_what_it_parses() {
+    let cases = [
+        ("A", "192.0.2.1"),
+        ("AAAA", "2001:db8::1"),
+        ("CNAME", "a\\.b\\032c.example."),
+        ("MX", "10 mx.example.com."),
+        (
+            "SOA",
+            "ns1.example.com. hostmaster.example.com. 2024010101 7200 3600 1209600 300",
+        ),
+        ("SRV", "0 5 5060 sip.example.com."),
+        ("TXT", "\"v=spf1 -all\" \"say \\\"hi\\\"\\010\""),
+        ("CAA", "0 issue \"ca.example.net; account=1\""),
+        ("DS", "2371 13 2 1F987CC6583E92DF0890718C42A2C8F2E3D5E7B2F8F3D1A0B2C3D4E5F6A7B8C9"),
+        ("DNSKEY", "257 3 13 mdsswUyr3DPW132mOi8V9xESWE8jTo0dxCjjnopKl+GqJxpVXckHAeF+KkxLbxILfDLUT0rAK9iUzy1L53eKGQ=="),
+        (
+            "RRSIG",
+            "A 13 2 300 20240201000000 20240101000000 12345 example.com. kW5Q5RSIFtS1lNqzS+a4c6LZQEyXrm5Cs3rQaATqWkEYHePgT7Yk9g==",
+        ),
+        (
+            "HTTPS",
+            "1 . alpn=h2,h3,a\\\\,b port=8443 ipv4hint=192.0.2.1,192.0.2.2 key65000=x\\032y",
+        ),
+        ("SVCB", "0 svc.example.com."),
+        ("TYPE65280", "\\# 3 ABCDEF"),
+    ];
+    for (record_type, text) in cases.iter() {
+        let record_type = parse_record_type(record_type).unwrap();
+        let lines = tokenize(text).unwrap();
+        let tokens = lines[0].iter().map(|v| v.text.as_str()).collect::<Vec<_>>();
+        let rdata = parse_record_data(&tokens, record_type).unwrap();
+        let formatted = format_record_data(&rdata, record_type);
+        assert_eq!(formatted, *text);
+    }
+}
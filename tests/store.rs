@@ -0,0 +1,121 @@
+mod common;
+
+use common::fqdn;
+use dns_collect::collect::{
+    AllDomains, AllQueries, Baseline, DomainStat, QueryOutcome, QueryStat, TtlObservation,
+};
+use dns_collect::container::{Content, Header};
+use dns_collect::presentation::parse_name;
+use dns_collect::record_wrapper::RecordWrapper;
+use dns_collect::store::Store;
+use std::net::Ipv4Addr;
+use std::time::{Duration, SystemTime};
+use trust_dns_proto::rr::{RData, Record, RecordType};
+
+fn at(seconds: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+fn header(content: Content, name_server: &str) -> Header {
+    Header {
+        record_type: "A".to_owned(),
+        name_server: name_server.to_owned(),
+        repeat: 10,
+        first_rank: 1,
+        last_rank: 2,
+        started_at: at(1_700_000_000),
+        finished_at: at(1_700_000_060),
+        ..Header::new(content)
+    }
+}
+
+#[test]
+fn reads_back_what_it_saved() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = Store::open(&dir.path().join("results.sqlite")).unwrap();
+
+    let name = fqdn("www.example");
+    let target = parse_name("a\\.b\\032c.example.").unwrap();
+    let cname = Record::from_rdata(name.clone(), 300, RData::CNAME(target.clone()));
+    let a = Record::from_rdata(target.clone(), 60, RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+    let mut all_domains = AllDomains::new();
+    for (record, ttl) in [(cname, 300), (a, 60)].iter() {
+        let stat = DomainStat {
+            counts: 2,
+            ttls: vec![*ttl, *ttl - 10].into_iter().collect(),
+            observations: vec![
+                TtlObservation {
+                    observed_at: at(1_700_000_010),
+                    ttl: *ttl,
+                },
+                TtlObservation {
+                    observed_at: at(1_700_000_020),
+                    ttl: *ttl - 10,
+                },
+            ],
+            original_ttl: Some(*ttl),
+        };
+        all_domains
+            .entry(record.name().clone())
+            .or_default()
+            .insert(RecordWrapper::new(record.clone()), stat);
+    }
+    store
+        .save_domains(&header(Content::AllDomains, "mock"), &all_domains)
+        .unwrap();
+
+    let outcome = QueryOutcome {
+        response_code: 0,
+        authoritative: false,
+        recursion_available: true,
+        truncated: false,
+        recursion_desired: false,
+        answer_count: 2,
+        authority_count: 0,
+        client_subnet_scope: Some(24),
+    };
+    let mut stat = QueryStat::default();
+    stat.outcomes.insert(outcome, 10);
+    stat.control = Some(QueryOutcome {
+        recursion_desired: true,
+        ..outcome
+    });
+    stat.latencies = vec![Duration::from_millis(12), Duration::from_millis(8)];
+    stat.chains.insert(vec![name.clone(), target.clone()], 10);
+    let mut all_queries = AllQueries::new();
+    all_queries.insert(name.clone(), stat);
+    store
+        .save_queries(&header(Content::AllQueries, "mock"), &all_queries)
+        .unwrap();
+
+    let mut baseline = Baseline::new();
+    baseline.insert((target.clone(), RecordType::A.into()), 60);
+    store
+        .save_baseline(&header(Content::Baseline, "auth"), &baseline)
+        .unwrap();
+
+    assert_eq!(store.read_domains("A", "mock").unwrap(), all_domains);
+    assert_eq!(store.read_queries("A", "mock").unwrap(), all_queries);
+    assert_eq!(store.read_baseline("A", "auth").unwrap(), baseline);
+    assert!(store.read_domains("A", "auth").unwrap().is_empty());
+    assert!(store.read_domains("AAAA", "mock").unwrap().is_empty());
+
+    let headers = store.headers().unwrap();
+    assert_eq!(headers.len(), 3);
+    assert_eq!(headers[0], header(Content::AllDomains, "mock"));
+    let done = header(Content::AllQueries, "mock");
+    assert_eq!(store.completed(&done).unwrap(), Some(done.clone()));
+    let later = Header {
+        first_rank: 3,
+        last_rank: 4,
+        ..done
+    };
+    assert_eq!(store.completed(&later).unwrap(), None);
+
+    // a chunk collected again replaces its earlier run
+    store
+        .save_queries(&header(Content::AllQueries, "mock"), &all_queries)
+        .unwrap();
+    assert_eq!(store.read_queries("A", "mock").unwrap(), all_queries);
+    assert_eq!(store.headers().unwrap().len(), 3);
+}
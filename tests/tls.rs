@@ -0,0 +1,120 @@
+mod common;
+
+use common::{fqdn, spawn_tls_server, ANSWER_ADDR, ANSWER_TTL};
+use dns_collect::collect::{collect, AllDomains, AllQueries};
+use dns_collect::name_server::NameServer;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tempfile::TempDir;
+use trust_dns_proto::rr::{RData, RecordType};
+
+const SERVER_NAME: &str = "dns.test";
+
+fn tls_name_server(
+    dir: &TempDir,
+    server_name: &str,
+    max_queries: usize,
+) -> (NameServer, Arc<AtomicUsize>) {
+    let rcgen::CertifiedKey { cert, signing_key } =
+        rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_owned()]).unwrap();
+    let ca_file = dir.path().join("ca.pem");
+    std::fs::write(&ca_file, cert.pem()).unwrap();
+    let (addr, connections) =
+        spawn_tls_server(cert.der().clone(), signing_key.serialize_der(), max_queries);
+    let name_server = serde_json::from_value(json!({
+        "name": "local-dot",
+        "host": "127.0.0.1",
+        "port": addr.port(),
+        "transport": "tls",
+        "tls_server_name": server_name,
+        "tls_ca_file": ca_file,
+    }))
+    .unwrap();
+    (name_server, connections)
+}
+
+#[tokio::test]
+async fn collect_over_tls() {
+    let dir = tempfile::tempdir().unwrap();
+    let (name_server, _) = tls_name_server(&dir, SERVER_NAME, usize::MAX);
+    let domain_names = vec!["example.com".to_owned(), "example.org".to_owned()];
+    let mut all_domains = AllDomains::new();
+    let meta = collect(
+        &name_server,
+        &domain_names,
+        RecordType::A,
+        3,
+        &mut all_domains,
+        &mut AllQueries::new(),
+    )
+    .await;
+    assert_eq!(meta.repeat_valid, 3);
+    assert_eq!(meta.response_valid, 6);
+    assert_eq!(meta.in_queries, 6);
+    assert_eq!(all_domains.len(), 2);
+    for domain_name in domain_names.iter() {
+        let records = &all_domains[&fqdn(domain_name)];
+        assert_eq!(records.len(), 1);
+        let (record, stat) = records.iter().next().unwrap();
+        assert_eq!(record.clone().unwrap().rdata(), &RData::A(ANSWER_ADDR));
+        assert_eq!(stat.counts, 3);
+        assert!(stat.ttls.contains(&ANSWER_TTL));
+    }
+}
+
+#[tokio::test]
+async fn collect_over_tls_rejects_wrong_server_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let (name_server, _) = tls_name_server(&dir, "other.test", usize::MAX);
+    let mut all_domains = AllDomains::new();
+    let meta = collect(
+        &name_server,
+        &["example.com".to_owned()],
+        RecordType::A,
+        1,
+        &mut all_domains,
+        &mut AllQueries::new(),
+    )
+    .await;
+    assert_eq!(meta.repeat_valid, 0);
+    assert!(all_domains.is_empty());
+}
+
+#[tokio::test]
+async fn collect_over_tls_reuses_connections() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mut name_server, connections) = tls_name_server(&dir, SERVER_NAME, usize::MAX);
+    name_server.max_in_flight = 1;
+    let meta = collect(
+        &name_server,
+        &["example.com".to_owned(), "example.org".to_owned()],
+        RecordType::A,
+        3,
+        &mut AllDomains::new(),
+        &mut AllQueries::new(),
+    )
+    .await;
+    assert_eq!(meta.response_valid, 6);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn collect_over_tls_reconnects_after_the_server_closes() {
+    let dir = tempfile::tempdir().unwrap();
+    let (mut name_server, connections) = tls_name_server(&dir, SERVER_NAME, 1);
+    name_server.max_in_flight = 1;
+    let meta = collect(
+        &name_server,
+        &["example.com".to_owned(), "example.org".to_owned()],
+        RecordType::A,
+        3,
+        &mut AllDomains::new(),
+        &mut AllQueries::new(),
+    )
+    .await;
+    // a closed connection is replaced without counting against the retries
+    assert_eq!(meta.response_valid, 6);
+    assert_eq!(meta.retries, 0);
+    assert_eq!(connections.load(Ordering::SeqCst), 6);
+}
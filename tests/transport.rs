@@ -0,0 +1,364 @@
+mod common;
+
+use async_trait::async_trait;
+use common::{answer, fqdn};
+use dns_collect::collect::{AllDomains, AllQueries, Collector};
+use dns_collect::error::QueryError;
+use dns_collect::rate_limit::{RateLimit, RateLimiter};
+use dns_collect::rdata::{parse_record_data, parse_record_type};
+use dns_collect::retry::RetryPolicy;
+use dns_collect::transport::{Response, Transport};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use trust_dns_proto::op::Message;
+use trust_dns_proto::rr::{Record, RecordType};
+
+/// Answers every other query and fails the rest.
+#[derive(Default)]
+struct FlakyTransport {
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl Transport for FlakyTransport {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError> {
+        assert!(!request.recursion_desired());
+        let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        if calls.is_multiple_of(2) {
+            return Err(QueryError::MismatchedResponse);
+        }
+        Ok(Response {
+            message: answer(request),
+            tcp_fallback: false,
+            setup: Duration::default(),
+        })
+    }
+}
+
+/// Answers after a delay, recording the largest number of queries outstanding at once.
+#[derive(Default)]
+struct SlowTransport {
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+#[async_trait]
+impl Transport for SlowTransport {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        Ok(Response {
+            message: answer(request),
+            tcp_fallback: false,
+            setup: Duration::default(),
+        })
+    }
+}
+
+/// Answers with an A record 600s into its TTL and an RRSIG giving the original TTL.
+struct SignedTransport;
+
+#[async_trait]
+impl Transport for SignedTransport {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError> {
+        let mut message = answer(request);
+        let name = request.queries()[0].name().clone();
+        let rrsig = "A 13 2 3600 20240201000000 20240101000000 2371 example.com. \
+                     kgd3WTPJ6Vj9A6ymPwJt4Qsx3Xp8Tn3Ww4yGXF7y1Ko="
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        let rrsig = parse_record_data(&rrsig, parse_record_type("RRSIG").unwrap()).unwrap();
+        let mut answers = message.take_answers();
+        answers[0].set_ttl(3000);
+        message.add_answers(answers);
+        message.add_answer(Record::from_rdata(name, 3000, rrsig));
+        Ok(Response {
+            message,
+            tcp_fallback: false,
+            setup: Duration::default(),
+        })
+    }
+}
+
+/// Takes 100ms to set up a connection for each query, then answers right away.
+struct ConnectingTransport;
+
+#[async_trait]
+impl Transport for ConnectingTransport {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError> {
+        let start = Instant::now();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok(Response {
+            message: answer(request),
+            tcp_fallback: false,
+            setup: start.elapsed(),
+        })
+    }
+}
+
+/// Never answers the first `stalls` queries, then answers the rest right away.
+struct StallingTransport {
+    stalls: usize,
+    calls: AtomicUsize,
+}
+
+impl StallingTransport {
+    fn new(stalls: usize) -> Self {
+        Self {
+            stalls,
+            calls: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for StallingTransport {
+    async fn query(&self, request: &Message) -> Result<Response, QueryError> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) < self.stalls {
+            futures::future::pending::<()>().await;
+        }
+        Ok(Response {
+            message: answer(request),
+            tcp_fallback: false,
+            setup: Duration::default(),
+        })
+    }
+}
+
+fn fast_retries(retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        timeout_ms: 50,
+        retries,
+        backoff_ms: 10,
+        max_backoff_ms: 40,
+    }
+}
+
+fn domain_names(n: usize) -> Vec<String> {
+    (0..n).map(|i| format!("host{}.example", i)).collect()
+}
+
+#[tokio::test]
+async fn collect_with_custom_transport() {
+    let transport = Arc::new(FlakyTransport::default());
+    let mut collector = Collector::with_transport(transport.clone(), 1);
+    collector.set_retry_policy(fast_retries(0));
+    let mut all_domains = AllDomains::new();
+    let meta = collector
+        .collect(
+            &["example.com".to_owned()],
+            RecordType::A,
+            4,
+            &mut all_domains,
+            &mut AllQueries::new(),
+        )
+        .await;
+    assert_eq!(transport.calls.load(Ordering::SeqCst), 4);
+    assert_eq!(meta.repeat_count, 4);
+    assert_eq!(meta.repeat_valid, 2);
+    let (_, stat) = all_domains[&fqdn("example.com")].iter().next().unwrap();
+    assert_eq!(stat.counts, 2);
+}
+
+#[tokio::test]
+async fn collect_with_bounds_in_flight_queries() {
+    let transport = Arc::new(SlowTransport::default());
+    let collector = Collector::with_transport(transport.clone(), 8);
+    let mut all_domains = AllDomains::new();
+    let meta = collector
+        .collect(
+            &domain_names(50),
+            RecordType::A,
+            2,
+            &mut all_domains,
+            &mut AllQueries::new(),
+        )
+        .await;
+    assert_eq!(meta.repeat_valid, 2);
+    assert_eq!(meta.response_valid, 100);
+    assert_eq!(all_domains.len(), 50);
+    assert!(all_domains
+        .values()
+        .all(|records| records.values().all(|stat| stat.counts == 2)));
+    assert_eq!(transport.max_in_flight.load(Ordering::SeqCst), 8);
+}
+
+#[tokio::test]
+async fn query_budget_spans_collect_calls() {
+    let transport = Arc::new(SlowTransport::default());
+    let mut collector = Collector::with_transport(transport, 4);
+    collector.set_rate_limiter(RateLimiter::new(None, Some(15)));
+    let mut all_domains = AllDomains::new();
+    let first = collector
+        .collect(
+            &domain_names(10),
+            RecordType::A,
+            1,
+            &mut all_domains,
+            &mut AllQueries::new(),
+        )
+        .await;
+    assert_eq!(first.repeat_valid, 1);
+    assert_eq!(first.budget_exhausted, 0);
+    let second = collector
+        .collect(
+            &domain_names(10),
+            RecordType::A,
+            1,
+            &mut all_domains,
+            &mut AllQueries::new(),
+        )
+        .await;
+    assert_eq!(second.repeat_valid, 0);
+    assert_eq!(second.response_valid, 5);
+    assert_eq!(second.budget_exhausted, 5);
+    assert_eq!(collector.rate_limiter().used(), 15);
+}
+
+#[tokio::test]
+async fn rate_limit_paces_queries() {
+    let transport = Arc::new(FlakyTransport::default());
+    let mut collector = Collector::with_transport(transport, 16);
+    collector.set_rate_limiter(RateLimiter::new(
+        Some(RateLimit {
+            queries_per_second: 50.0,
+            burst: 2,
+        }),
+        None,
+    ));
+    let mut all_domains = AllDomains::new();
+    let start = Instant::now();
+    let meta = collector
+        .collect(
+            &domain_names(12),
+            RecordType::A,
+            1,
+            &mut all_domains,
+            &mut AllQueries::new(),
+        )
+        .await;
+    // the first 2 queries use up the burst, the other 10 are spaced 20ms apart
+    assert!(start.elapsed() >= Duration::from_millis(190));
+    assert!(meta.throttled >= Duration::from_millis(190));
+    assert_eq!(meta.budget_exhausted, 0);
+}
+
+#[tokio::test]
+async fn timed_out_queries_are_retried() {
+    let transport = Arc::new(StallingTransport::new(2));
+    let mut collector = Collector::with_transport(transport.clone(), 1);
+    collector.set_retry_policy(fast_retries(2));
+    let mut all_domains = AllDomains::new();
+    let meta = collector
+        .collect(
+            &domain_names(1),
+            RecordType::A,
+            1,
+            &mut all_domains,
+            &mut AllQueries::new(),
+        )
+        .await;
+    assert_eq!(transport.calls.load(Ordering::SeqCst), 3);
+    assert_eq!(meta.timeouts, 2);
+    assert_eq!(meta.retries, 2);
+    assert_eq!(meta.give_ups, 0);
+    assert_eq!(meta.repeat_valid, 1);
+    assert_eq!(all_domains.len(), 1);
+}
+
+#[tokio::test]
+async fn gives_up_after_last_retry() {
+    let transport = Arc::new(StallingTransport::new(usize::MAX));
+    let mut collector = Collector::with_transport(transport.clone(), 4);
+    collector.set_retry_policy(fast_retries(1));
+    let mut all_domains = AllDomains::new();
+    let meta = collector
+        .collect(
+            &domain_names(3),
+            RecordType::A,
+            1,
+            &mut all_domains,
+            &mut AllQueries::new(),
+        )
+        .await;
+    assert_eq!(transport.calls.load(Ordering::SeqCst), 6);
+    assert_eq!(meta.timeouts, 6);
+    assert_eq!(meta.retries, 3);
+    assert_eq!(meta.give_ups, 3);
+    assert_eq!(meta.repeat_valid, 0);
+    assert!(all_domains.is_empty());
+}
+
+#[test]
+fn backoff_grows_exponentially_up_to_the_cap() {
+    let policy = RetryPolicy {
+        timeout_ms: 1000,
+        retries: 8,
+        backoff_ms: 100,
+        max_backoff_ms: 1000,
+    };
+    for _ in 0..20 {
+        let first = policy.backoff(1);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let third = policy.backoff(3);
+        assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+        let capped = policy.backoff(8);
+        assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_millis(1000));
+    }
+}
+
+#[tokio::test]
+async fn takes_original_ttl_from_rrsig() {
+    let collector = Collector::with_transport(Arc::new(SignedTransport), 1);
+    let mut all_domains = AllDomains::new();
+    collector
+        .collect(
+            &["example.com".to_owned()],
+            RecordType::A,
+            2,
+            &mut all_domains,
+            &mut AllQueries::new(),
+        )
+        .await;
+    let records = &all_domains[&fqdn("example.com")];
+    assert_eq!(records.len(), 2);
+    let (_, stat) = records
+        .iter()
+        .find(|(record, _)| (*record).clone().unwrap().rr_type() == RecordType::A)
+        .unwrap();
+    assert_eq!(stat.original_ttl, Some(3600));
+    assert_eq!(stat.observations.len(), 2);
+    assert!(stat.observations.iter().all(|v| v.ttl == 3000));
+    // both answers point back to the same fill, 600s before they came in
+    let fills = stat.cache_fills(3600);
+    assert_eq!(fills.len(), 1);
+    let age = stat.observations[0]
+        .observed_at
+        .duration_since(fills[0])
+        .unwrap();
+    assert_eq!(age, Duration::from_secs(600));
+}
+
+#[tokio::test]
+async fn leaves_connection_setup_out_of_the_latency() {
+    let collector = Collector::with_transport(Arc::new(ConnectingTransport), 1);
+    let mut all_queries = AllQueries::new();
+    collector
+        .collect(
+            &["example.com".to_owned()],
+            RecordType::A,
+            2,
+            &mut AllDomains::new(),
+            &mut all_queries,
+        )
+        .await;
+    let stat = &all_queries[&fqdn("example.com")];
+    assert_eq!(stat.latencies.len(), 2);
+    assert!(stat
+        .latencies
+        .iter()
+        .all(|v| *v < Duration::from_millis(100)));
+}